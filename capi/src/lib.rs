@@ -0,0 +1,3 @@
+//! Links the C API of `avif-parse` into `libavif_parse.a`. See `avif_parse.h`.
+
+pub use avif_parse_rs::c_api::*;
@@ -0,0 +1,196 @@
+use crate::boxes::BoxType;
+use crate::{
+    assign_items, check_ftyp, init_data, read_avif_meta, read_box_header, read_idat_items, slice_box, sorted_file_extents, AvifData,
+    AvifHeader, BoxHeader, Error, ErrorContext, FileType, ItemSelection, MediaDataBox, OffsetReader, ParseContext, ParseOptions, Result,
+    ToU64, TryVec,
+};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Read the contents of an AVIF file from an async reader
+///
+/// See [`AvifHeader::from_async_reader()`]
+pub async fn read_avif_async<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<AvifData> {
+    AvifHeader::from_async_reader(reader).await?.read_avif_async().await
+}
+
+/// Like [`read_avif_async()`], but fails with [`Error::LimitExceeded`] if the file exceeds the limits
+///
+/// Errors come with the location of the box they're in.
+pub async fn read_avif_async_with_options<R: AsyncRead + Unpin + ?Sized>(reader: &mut R, options: &ParseOptions) -> Result<AvifData, ErrorContext> {
+    Ok(AvifHeader::from_async_reader_with_options(reader, options).await?.read_avif_async().await?)
+}
+
+/// Parse AVIF header from an async reader
+impl<R: AsyncRead + Unpin> AvifHeader<R> {
+    /// Like [`AvifHeader::from_reader()`], but doesn't block. Wrap unbuffered readers in [`tokio::io::BufReader`].
+    pub async fn from_async_reader(reader: R) -> Result<Self> {
+        Ok(Self::from_async_reader_with(reader, ParseContext::default()).await?)
+    }
+
+    /// Like [`AvifHeader::from_async_reader()`], with limits for untrusted files. The limits also apply to [`AvifHeader::read_avif_async()`].
+    ///
+    /// Errors come with the location of the box they're in.
+    pub async fn from_async_reader_with_options(reader: R, options: &ParseOptions) -> Result<Self, ErrorContext> {
+        Self::from_async_reader_with(reader, ParseContext::new(options)).await
+    }
+
+    async fn from_async_reader_with(reader: R, mut ctx: ParseContext) -> Result<Self, ErrorContext> {
+        Self::from_async_reader_in(reader, &mut ctx).await.map_err(|e| ctx.error_context(e))
+    }
+
+    async fn from_async_reader_in(reader: R, ctx: &mut ParseContext) -> Result<Self> {
+        let mut src = OffsetReader::new(reader);
+
+        // 'ftyp' box must occur first; see ISO 14496-12:2015 § 4.3.1
+        let mut file_type = FileType::default();
+        if let Some(head) = src.next_box_header().await? {
+            file_type = check_ftyp(head, &src.read_box_content(head, ctx).await?, ctx)?;
+        }
+
+        let mut mdats = TryVec::new();
+        loop {
+            let head = src.next_box_header().await?.ok_or(Error::InvalidData("No MediaDataBox"))?;
+            ctx.check_file_size((src.offset - head.offset).saturating_add(head.size))?;
+            match head.name {
+                BoxType::MetadataBox => {
+                    let start = src.offset - head.offset;
+                    let meta = read_avif_meta(slice_box(head, &src.read_box_content(head, ctx).await?, start), ctx)?;
+                    let mut data = init_data(&meta);
+                    data.file_type = file_type;
+                    return Ok(Self {
+                        meta, data, mdats, skipped_mdats: TryVec::new(), offset_reader: src, ctx: core::mem::take(ctx), selection: ItemSelection::default(),
+                    });
+                },
+                // annoyingly, mdat is allowed to appear before meta
+                BoxType::MediaDataBox => {
+                    if head.size > head.offset {
+                        let offset = src.offset;
+                        let data = src.read_box_content(head, ctx).await?;
+                        mdats.push(MediaDataBox { offset, data })?;
+                    }
+                },
+                _ => src.skip_box_content(head).await?,
+            }
+        }
+    }
+
+    /// Read the rest of the file without blocking
+    pub async fn read_avif_async(self) -> Result<AvifData> {
+        let item_ids = self.selected_item_ids()?;
+        let Self { meta, mut offset_reader, mdats, data: mut context, mut ctx, .. } = self;
+
+        meta.check_not_protected(item_ids.iter().copied())?;
+        let mut items = TryVec::with_capacity(item_ids.len())?;
+        for _ in item_ids.iter() {
+            items.push(TryVec::new())?;
+        }
+        read_idat_items(&meta, &item_ids, &mut items, &mut ctx)?;
+        let mut mdats = mdats.into_iter().fuse(); // reading automatically sorted mdats by offset
+        let mut current_mdat: Option<MediaDataBox> = None;
+        for (index, range) in sorted_file_extents(&meta, &item_ids)? {
+            // try to find an overlapping mdat
+            let mdat = loop {
+                match &mut current_mdat {
+                    Some(mdat) if mdat.contains_extent(&range) => break mdat,
+                    _ => {
+                        current_mdat = mdats.next();
+                        if current_mdat.is_none() {
+                            current_mdat = Some(offset_reader.next_data_box(&mut ctx).await?);
+                        }
+                    },
+                }
+            };
+
+            let item_data = &mut items[index];
+            if item_data.is_empty() && mdat.matches_extent(&range) {
+                *item_data = std::mem::take(&mut mdat.data);
+            } else {
+                ctx.allocate(mdat.extent_data_range(&range)?.len().to_u64())?;
+                mdat.read_extent(&range, item_data)?;
+            }
+        }
+        assign_items(&meta, &mut context, &item_ids, items)?;
+
+        ctx.check_av1_dimensions(&context.primary_item, context.alpha_item.as_deref())?;
+        context.fill_hdr_from_obus();
+        context.warnings = ctx.warnings;
+        Ok(context)
+    }
+}
+
+impl<R: AsyncRead + Unpin> OffsetReader<R> {
+    /// Like `BoxIter::next_box`, returns `None` at the end of the file
+    async fn next_box_header(&mut self) -> Result<Option<BoxHeader>> {
+        let mut header = [0u8; 32];
+        let min_len = BoxHeader::MIN_SIZE as usize;
+        if self.read_up_to(&mut header[..min_len]).await? < min_len {
+            return Ok(None);
+        }
+        let mut len = min_len;
+        if header[..4] == 1u32.to_be_bytes() {
+            len = BoxHeader::MIN_LARGE_SIZE as usize;
+        }
+        if BoxType::from(u32::from_be_bytes([header[4], header[5], header[6], header[7]])) == BoxType::UuidBox {
+            len += 16;
+        }
+        if self.read_up_to(&mut header[min_len..len]).await? < len - min_len {
+            return Ok(None);
+        }
+        read_box_header(&mut &header[..len]).map(Some)
+    }
+
+    async fn read_up_to(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut total = 0;
+        while total < buf.len() {
+            let n = self.reader.read(&mut buf[total..]).await?;
+            if n == 0 {
+                break;
+            }
+            total += n;
+        }
+        self.offset += total.to_u64();
+        Ok(total)
+    }
+
+    /// Like `BMFFBox::read_into_try_vec`, a truncated box is read up to the end of the file
+    async fn read_box_content(&mut self, head: BoxHeader, ctx: &mut ParseContext) -> Result<TryVec<u8>> {
+        let len = head.size - head.offset;
+        ctx.allocate(len)?;
+        let mut vec = std::vec::Vec::new();
+        vec.try_reserve_exact(len.try_into()?)?;
+        let read = (&mut self.reader).take(len).read_to_end(&mut vec).await?;
+        self.offset += read.to_u64();
+        Ok(vec.into())
+    }
+
+    async fn skip_box_content(&mut self, head: BoxHeader) -> Result<()> {
+        let len = head.size - head.offset;
+        let skipped = tokio::io::copy(&mut (&mut self.reader).take(len), &mut tokio::io::sink()).await?;
+        self.offset += skipped;
+        if skipped < len {
+            return Err(Error::UnexpectedEOF);
+        }
+        Ok(())
+    }
+
+    /// See `next_data_box`
+    async fn next_data_box(&mut self, ctx: &mut ParseContext) -> Result<MediaDataBox> {
+        loop {
+            let head = self.next_box_header().await?.ok_or(Error::InvalidData("No MediaDataBox"))?;
+            ctx.check_file_size((self.offset - head.offset).saturating_add(head.size))?;
+            match head.name {
+                BoxType::MetadataBox => {
+                    return Err(Error::InvalidData("There should be zero or one meta boxes per ISO 14496-12:2015 § 8.11.1.1"));
+                },
+                BoxType::MediaDataBox if head.size > head.offset => {
+                    let offset = self.offset;
+                    let data = self.read_box_content(head, ctx).await?;
+                    ctx.check_truncated_mdat(data.len().to_u64() < head.size - head.offset)?;
+                    return Ok(MediaDataBox { offset, data });
+                },
+                _ => self.skip_box_content(head).await?,
+            }
+        }
+    }
+}
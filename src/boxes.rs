@@ -1,7 +1,7 @@
 // This Source Code Form is subject to the terms of the Mozilla Public
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
-use std::fmt;
+use core::fmt;
 
 // To ensure we don't use stdlib allocating types by accident
 #[allow(dead_code)]
@@ -78,7 +78,7 @@ impl From<[u8; 4]> for FourCC {
 
 impl fmt::Debug for FourCC {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match std::str::from_utf8(&self.value) {
+        match core::str::from_utf8(&self.value) {
             Ok(s) => f.write_str(s),
             Err(_) => self.value.fmt(f),
         }
@@ -87,7 +87,7 @@ impl fmt::Debug for FourCC {
 
 impl fmt::Display for FourCC {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(std::str::from_utf8(&self.value).unwrap_or("null"))
+        f.write_str(core::str::from_utf8(&self.value).unwrap_or("null"))
     }
 }
 
@@ -104,6 +104,8 @@ box_database!(
     ItemInfoBox                       0x6969_6e66, // "iinf"
     ItemInfoEntry                     0x696e_6665, // "infe"
     ItemLocationBox                   0x696c_6f63, // "iloc"
+    ItemDataBox                       0x6964_6174, // "idat"
+    ItemProtectionBox                 0x6970_726f, // "ipro"
     MovieBox                          0x6d6f_6f76, // "moov"
     MovieHeaderBox                    0x6d76_6864, // "mvhd"
     TrackBox                          0x7472_616b, // "trak"
@@ -114,12 +116,16 @@ box_database!(
     MediaHeaderBox                    0x6d64_6864, // "mdhd"
     HandlerBox                        0x6864_6c72, // "hdlr"
     MediaInformationBox               0x6d69_6e66, // "minf"
+    DataInformationBox                0x6469_6e66, // "dinf"
+    DataReferenceBox                  0x6472_6566, // "dref"
+    DataEntryUrlBox                   0x7572_6c20, // "url "
     ImageReferenceBox                 0x6972_6566, // "iref"
     ImagePropertiesBox                0x6970_7270, // "iprp"
     ItemPropertyContainerBox          0x6970_636f, // "ipco"
     ItemPropertyAssociationBox        0x6970_6d61, // "ipma"
     ColorInformationBox               0x636f_6c72, // "colr"
     PixelInformationBox               0x7069_7869, // "pixi"
+    ImageSpatialExtentsProperty       0x6973_7065, // "ispe"
     AuxiliaryTypeProperty             0x6175_7843, // "auxC"
     ContentLightLevelBox              0x636c_6c69, // "clli"
     MasteringDisplayColourVolumeBox   0x6d64_6376, // "mdcv"
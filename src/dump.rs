@@ -0,0 +1,319 @@
+use crate::boxes::{BoxType, FourCC};
+use core::fmt;
+use crate::{
+    be_u32, read_box_header_to_end, read_fullbox_version_no_flags, read_ftyp, read_iloc,
+    read_infe, read_ipco_child, read_ipma, read_ispe, read_item_reference, read_pitm, read_sinf, slice_box, BoxHeader,
+    ConstructionMethod, ContentLightLevel, Error, FileType, ImageSpatialExtents, ItemExtent, ItemProperty,
+    MasteringDisplayColourVolume, ParseContext, ParseOptions, ProtectionScheme, Result, Strictness, ToU64, TryString,
+    TryVec,
+};
+
+/// Boxes nested deeper than this are not listed
+const MAX_DEPTH: usize = 64;
+
+/// All boxes of a file, see [`dump_boxes()`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct BoxTree {
+    /// Top-level boxes
+    pub boxes: TryVec<BoxNode>,
+    /// Why the data after the last top-level box couldn't be read as a box
+    pub error: Option<Error>,
+}
+
+/// A box and its children, see [`dump_boxes()`]
+#[non_exhaustive]
+pub struct BoxNode {
+    /// Four-character code, e.g. `ftyp`
+    pub box_type: [u8; 4],
+    /// Position of the box header in the file
+    pub offset: u64,
+    /// 8 bytes, 16 with a 64-bit size, and 16 more for `uuid` boxes
+    pub header_size: u64,
+    /// Size of the box after the header. For a last box with size 0 it's the rest of the file.
+    pub payload_size: u64,
+    /// The box claims to be longer than the file. Its fields are not parsed.
+    pub truncated: bool,
+    /// Version and 24-bit flags of full boxes known to this crate
+    pub version_flags: Option<(u8, u32)>,
+    /// Parsed contents of the box, if this crate knows its type
+    pub fields: BoxFields,
+    /// Boxes nested in this one, in file order
+    pub children: TryVec<BoxNode>,
+    /// Why the fields or children of this box couldn't be read
+    pub error: Option<Error>,
+}
+
+impl fmt::Debug for BoxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoxNode")
+            .field("box_type", &FourCC::from(self.box_type))
+            .field("offset", &self.offset)
+            .field("header_size", &self.header_size)
+            .field("payload_size", &self.payload_size)
+            .field("truncated", &self.truncated)
+            .field("version_flags", &self.version_flags)
+            .field("fields", &self.fields)
+            .field("children", &self.children)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+/// Contents of boxes that this crate can parse, see [`BoxNode::fields`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BoxFields {
+    /// The box isn't parsed by this crate, or couldn't be parsed
+    None,
+    /// `ftyp`
+    FileType(FileType),
+    /// `pitm`
+    PrimaryItem(u32),
+    /// `hdlr`, e.g. `pict` for images
+    Handler([u8; 4]),
+    /// `infe`
+    ItemInfo {
+        item_id: u32,
+        item_type: [u8; 4],
+        hidden: bool,
+        /// 1-based index of the `sinf` box in `ipro`, 0 if not protected
+        protection_index: u16,
+        name: TryVec<u8>,
+        /// Only for `mime` items
+        content_type: Option<TryVec<u8>>,
+    },
+    /// `iloc`
+    ItemLocations(TryVec<ItemLocation>),
+    /// Child of `iref`. The reference type is the box type.
+    ItemReference { from_item_id: u32, to_item_ids: TryVec<u32> },
+    /// `ipma`
+    PropertyAssociations(TryVec<PropertyAssociation>),
+    /// `ispe`
+    ImageSpatialExtents(ImageSpatialExtents),
+    /// `pixi`, bits per channel
+    PixelInformation(TryVec<u8>),
+    /// `auxC`
+    AuxiliaryType { aux_type: TryVec<u8>, subtype: TryVec<u8> },
+    /// `clli`
+    ContentLightLevel(ContentLightLevel),
+    /// `mdcv`
+    MasteringDisplayColourVolume(MasteringDisplayColourVolume),
+    /// `sinf`
+    ProtectionScheme(ProtectionScheme),
+}
+
+/// Entry of the `iloc` box, see [`BoxFields::ItemLocations`]
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub struct ItemLocation {
+    /// Item the data belongs to
+    pub item_id: u32,
+    /// Whether the offsets are in the file or in `idat`
+    pub construction_method: ConstructionMethod,
+    /// Pieces of the item data, to be concatenated in this order
+    pub extents: TryVec<ItemExtent>,
+}
+
+/// Entry of the `ipma` box, see [`BoxFields::PropertyAssociations`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PropertyAssociation {
+    /// Item the property applies to
+    pub item_id: u32,
+    /// The item can't be processed by readers that don't understand the property
+    pub essential: bool,
+    /// 1-based index of the property in `ipco`, 0 for no property
+    pub property_index: u16,
+}
+
+/// Lists all boxes of an ISOBMFF file (AVIF, HEIF, MP4, etc.), for debugging.
+///
+/// The file doesn't have to be valid AVIF. Damaged boxes are listed with an `error`, and the walk continues
+/// with the next box when the size of the damaged box is known. Media data is not read.
+///
+/// Fails only when out of memory. Use `{:#?}` to print the tree.
+pub fn dump_boxes(data: &[u8]) -> Result<BoxTree> {
+    let mut ctx = ParseContext::new(&ParseOptions { strictness: Strictness::Lenient, ..ParseOptions::default() });
+    let (boxes, error) = read_boxes(data, 0, 0, None, &mut ctx)?;
+    Ok(BoxTree { boxes, error })
+}
+
+/// Boxes in `data`, which is at `start` in the file.
+/// `iref_version` is set for children of `iref`, which need it to be parsed.
+fn read_boxes(data: &[u8], start: u64, depth: usize, iref_version: Option<u8>, ctx: &mut ParseContext) -> Result<(TryVec<BoxNode>, Option<Error>)> {
+    let mut boxes = TryVec::new();
+    let mut pos = 0;
+    while let Some(rest) = data.get(pos..).filter(|rest| !rest.is_empty()) {
+        // Only the last top-level box may extend to the end of the file
+        let size_to_end = (depth == 0).then(|| rest.len().to_u64());
+        let head = match read_box_header_to_end(&mut &rest[..], size_to_end) {
+            Ok(head) => head,
+            Err(Error::OutOfMemory) => return Err(Error::OutOfMemory),
+            Err(e) => return Ok((boxes, Some(e))),
+        };
+        let end = usize::try_from(head.size).unwrap_or(usize::MAX).min(rest.len());
+        let payload = rest.get(usize::try_from(head.offset)?..end).unwrap_or_default();
+        boxes.push(read_node(head, payload, start + pos.to_u64(), depth, iref_version, ctx)?)?;
+        pos += end;
+    }
+    Ok((boxes, None))
+}
+
+fn read_node(head: BoxHeader, payload: &[u8], offset: u64, depth: usize, iref_version: Option<u8>, ctx: &mut ParseContext) -> Result<BoxNode> {
+    let payload_size = head.size - head.offset;
+    let truncated = payload.len().to_u64() < payload_size;
+    let children_offset = children_offset(head.name, payload);
+    let is_full_box = match head.name {
+        BoxType::MetadataBox => children_offset == Some(4),
+        name => is_full_box(name),
+    };
+    let version_flags = payload.get(..4).filter(|_| is_full_box)
+        .map(|b| (b[0], u32::from_be_bytes([0, b[1], b[2], b[3]])));
+
+    let mut error = None;
+    let fields = if truncated {
+        BoxFields::None
+    } else {
+        let mut b = slice_box(head, payload, offset);
+        match read_fields(&mut b, iref_version, ctx) {
+            Ok(fields) => fields,
+            Err(Error::OutOfMemory) => return Err(Error::OutOfMemory),
+            Err(e) => {
+                error = Some(e);
+                BoxFields::None
+            },
+        }
+    };
+
+    let mut children = TryVec::new();
+    if let Some(children_offset) = children_offset {
+        if depth >= MAX_DEPTH {
+            error = error.or(Some(Error::InvalidData("boxes are nested too deeply")));
+        } else {
+            let iref_version = (head.name == BoxType::ImageReferenceBox).then(|| payload.first().copied().unwrap_or_default());
+            let children_data = payload.get(children_offset..).unwrap_or_default();
+            let children_start = offset + head.offset + children_offset.to_u64();
+            let (nodes, children_error) = read_boxes(children_data, children_start, depth + 1, iref_version, ctx)?;
+            children = nodes;
+            error = error.or(children_error);
+        }
+    }
+
+    Ok(BoxNode {
+        box_type: u32::from(head.name).to_be_bytes(),
+        offset,
+        header_size: head.offset,
+        payload_size,
+        truncated,
+        version_flags,
+        fields,
+        children,
+        error,
+    })
+}
+
+fn read_fields(b: &mut crate::BMFFBox<&[u8]>, iref_version: Option<u8>, ctx: &mut ParseContext) -> Result<BoxFields> {
+    if let Some(version) = iref_version {
+        let (from_item_id, to_item_ids) = read_item_reference(b, version)?;
+        return Ok(BoxFields::ItemReference { from_item_id, to_item_ids });
+    }
+    Ok(match b.head.name {
+        BoxType::FileTypeBox => BoxFields::FileType(read_ftyp(b)?),
+        BoxType::PrimaryItemBox => BoxFields::PrimaryItem(read_pitm(b)?),
+        BoxType::HandlerBox => {
+            read_fullbox_version_no_flags(b)?;
+            let _pre_defined = be_u32(b)?;
+            BoxFields::Handler(be_u32(b)?.to_be_bytes())
+        },
+        BoxType::ItemInfoEntry => {
+            let infe = read_infe(b, ctx)?;
+            BoxFields::ItemInfo {
+                item_id: infe.item_id,
+                item_type: infe.item_type.value,
+                hidden: infe.hidden,
+                protection_index: infe.protection_index,
+                name: infe.name,
+                content_type: infe.content_type,
+            }
+        },
+        BoxType::ItemLocationBox => {
+            let mut locations = TryVec::new();
+            for item in read_iloc(b, ctx)? {
+                let mut extents = TryVec::with_capacity(item.extents.len())?;
+                for extent in &item.extents {
+                    extents.push(ItemExtent::from(&extent.extent_range))?;
+                }
+                locations.push(ItemLocation { item_id: item.item_id, construction_method: item.construction_method, extents })?;
+            }
+            BoxFields::ItemLocations(locations)
+        },
+        BoxType::ItemPropertyAssociationBox => {
+            let mut associations = TryVec::new();
+            for a in read_ipma(b, ctx)? {
+                associations.push(PropertyAssociation { item_id: a.item_id, essential: a.essential, property_index: a.property_index })?;
+            }
+            BoxFields::PropertyAssociations(associations)
+        },
+        BoxType::ImageSpatialExtentsProperty => BoxFields::ImageSpatialExtents(read_ispe(b)?),
+        BoxType::PixelInformationBox | BoxType::AuxiliaryTypeProperty | BoxType::ContentLightLevelBox | BoxType::MasteringDisplayColourVolumeBox => {
+            match read_ipco_child(b)? {
+                ItemProperty::Channels(bits) => BoxFields::PixelInformation(to_try_vec(&bits)?),
+                ItemProperty::AuxiliaryType(aux) => {
+                    let (aux_type, subtype) = aux.type_subtype();
+                    BoxFields::AuxiliaryType { aux_type: to_try_vec(aux_type)?, subtype: to_try_vec(subtype)? }
+                },
+                ItemProperty::ContentLightLevel(clli) => BoxFields::ContentLightLevel(clli),
+                ItemProperty::MasteringDisplayColourVolume(mdcv) => BoxFields::MasteringDisplayColourVolume(mdcv),
+                ItemProperty::ImageSpatialExtents(_) | ItemProperty::Unsupported => BoxFields::None,
+            }
+        },
+        BoxType::ProtectionSchemeInfoBox => BoxFields::ProtectionScheme(read_sinf(b, ctx)?),
+        _ => BoxFields::None,
+    })
+}
+
+fn to_try_vec(data: &[u8]) -> Result<TryString> {
+    let mut vec = TryVec::new();
+    vec.extend_from_slice(data)?;
+    Ok(vec)
+}
+
+/// Where the child boxes start in the payload, if the box has children
+fn children_offset(name: BoxType, payload: &[u8]) -> Option<usize> {
+    Some(match name {
+        BoxType::MovieBox | BoxType::TrackBox | BoxType::EditBox | BoxType::MediaBox | BoxType::MediaInformationBox
+        | BoxType::DataInformationBox | BoxType::SampleTableBox | BoxType::MovieExtendsBox | BoxType::UserdataBox
+        | BoxType::ImagePropertiesBox | BoxType::ItemPropertyContainerBox | BoxType::ProtectionSchemeInfoBox
+        | BoxType::SchemeInformationBox => 0,
+        // QuickTime's meta is not a full box
+        BoxType::MetadataBox if payload.get(4..8) == Some(&b"hdlr"[..]) => 0,
+        BoxType::MetadataBox | BoxType::ImageReferenceBox => 4,
+        // version, flags and entry count
+        BoxType::ItemInfoBox if payload.first() == Some(&0) => 4 + 2,
+        BoxType::ItemInfoBox => 4 + 4,
+        BoxType::ItemProtectionBox => 4 + 2,
+        BoxType::DataReferenceBox | BoxType::SampleDescriptionBox => 4 + 4,
+        // See ISO 14496-12:2015 § 12.1.3 and § 12.2.3
+        BoxType::AV1SampleEntry | BoxType::AVCSampleEntry | BoxType::AVC3SampleEntry | BoxType::VP8SampleEntry
+        | BoxType::VP9SampleEntry | BoxType::MP4VideoSampleEntry | BoxType::ProtectedVisualSampleEntry => 78,
+        BoxType::MP4AudioSampleEntry | BoxType::OpusSampleEntry | BoxType::FLACSampleEntry
+        | BoxType::ProtectedAudioSampleEntry => 28,
+        _ => return None,
+    })
+}
+
+/// Boxes that start with a version and flags, except `meta`
+fn is_full_box(name: BoxType) -> bool {
+    matches!(name,
+        BoxType::PrimaryItemBox | BoxType::ItemInfoBox | BoxType::ItemInfoEntry | BoxType::ItemLocationBox
+        | BoxType::ImageReferenceBox | BoxType::ItemPropertyAssociationBox | BoxType::ImageSpatialExtentsProperty
+        | BoxType::PixelInformationBox | BoxType::AuxiliaryTypeProperty | BoxType::HandlerBox
+        | BoxType::ItemProtectionBox | BoxType::SchemeTypeBox | BoxType::TrackEncryptionBox
+        | BoxType::MovieHeaderBox | BoxType::TrackHeaderBox | BoxType::MediaHeaderBox | BoxType::EditListBox
+        | BoxType::SampleDescriptionBox | BoxType::TimeToSampleBox | BoxType::SampleToChunkBox
+        | BoxType::SampleSizeBox | BoxType::ChunkOffsetBox | BoxType::ChunkLargeOffsetBox | BoxType::SyncSampleBox
+        | BoxType::CompositionOffsetBox | BoxType::MovieExtendsHeaderBox | BoxType::ProtectionSystemSpecificHeaderBox
+        | BoxType::DataReferenceBox | BoxType::DataEntryUrlBox | BoxType::ESDBox | BoxType::VPCodecConfigurationBox
+        | BoxType::FLACSpecificBox | BoxType::MetadataItemKeysBox)
+}
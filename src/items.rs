@@ -0,0 +1,193 @@
+use crate::{
+    AvifHeader, AvifInternalMeta, ConstructionMethod, ContentLightLevel, ExtentRange, ImageSpatialExtents,
+    ItemInfoEntry, ItemLocationBoxItem, ItemProperty, MasteringDisplayColourVolume, ProtectionScheme,
+};
+
+/// Read-only view of the `meta` box contents.
+///
+/// Items are listed in the order of the `iinf` box, including items this crate doesn't use.
+impl<R> AvifHeader<R> {
+    /// All items in the file, see [`ItemInfo`]
+    pub fn items(&self) -> impl Iterator<Item = ItemInfo<'_>> {
+        let meta = &self.meta;
+        meta.item_infos.iter().map(move |info| ItemInfo::new(meta, info))
+    }
+
+    /// Item with the given ID, if it's listed in `iinf`
+    #[must_use]
+    pub fn item(&self, item_id: u32) -> Option<ItemInfo<'_>> {
+        let info = self.meta.item_infos.iter().find(|info| info.item_id == item_id)?;
+        Some(ItemInfo::new(&self.meta, info))
+    }
+
+    /// ID of the item from the `pitm` box
+    #[must_use]
+    pub fn primary_item_id(&self) -> u32 {
+        self.meta.primary_item_id
+    }
+}
+
+/// Item from the `iinf` box, with its location, references and properties.
+///
+/// See [`AvifHeader::items()`]
+#[derive(Clone, Copy)]
+pub struct ItemInfo<'a> {
+    meta: &'a AvifInternalMeta,
+    info: &'a ItemInfoEntry,
+    location: Option<&'a ItemLocationBoxItem>,
+}
+
+impl<'a> ItemInfo<'a> {
+    fn new(meta: &'a AvifInternalMeta, info: &'a ItemInfoEntry) -> Self {
+        let location = meta.iloc_items.iter().find(|loc| loc.item_id == info.item_id);
+        Self { meta, info, location }
+    }
+
+    /// `item_ID` used by `pitm`, `iloc`, `iref` and `ipma`
+    #[must_use]
+    pub fn id(&self) -> u32 {
+        self.info.item_id
+    }
+
+    /// `av01`, `grid`, `Exif`, `mime`, etc.
+    #[must_use]
+    pub fn item_type(&self) -> [u8; 4] {
+        self.info.item_type.value
+    }
+
+    /// Usually empty
+    #[must_use]
+    pub fn name(&self) -> &'a [u8] {
+        &self.info.name
+    }
+
+    /// MIME type of `mime` items, e.g. `application/rdf+xml` for XMP
+    #[must_use]
+    pub fn content_type(&self) -> Option<&'a [u8]> {
+        self.info.content_type.as_deref()
+    }
+
+    /// Hidden items are not meant to be displayed on their own
+    #[must_use]
+    pub fn is_hidden(&self) -> bool {
+        self.info.hidden
+    }
+
+    /// Encrypted items can't be decoded, but their data can be read with [`AvifHeader::read_item()`]
+    #[must_use]
+    pub fn is_protected(&self) -> bool {
+        self.info.protection_index != 0
+    }
+
+    /// Scheme and key of an encrypted item. `None` if the item isn't protected, or the `ipro` box is missing.
+    #[must_use]
+    pub fn protection(&self) -> Option<&'a ProtectionScheme> {
+        let index = usize::from(self.info.protection_index).checked_sub(1)?;
+        self.meta.protection_schemes.get(index)
+    }
+
+    /// `None` if the item is missing from `iloc`
+    #[must_use]
+    pub fn construction_method(&self) -> Option<ConstructionMethod> {
+        self.location.map(|loc| loc.construction_method)
+    }
+
+    /// Where the item's data is. Offsets are relative to the file, or to the `idat` box for [`ConstructionMethod::Idat`].
+    pub fn extents(&self) -> impl Iterator<Item = ItemExtent> + 'a {
+        self.location.into_iter().flat_map(|loc| loc.extents.iter()).map(|extent| ItemExtent::from(&extent.extent_range))
+    }
+
+    /// References from this item to other items, e.g. `auxl` from alpha to the color item
+    pub fn references(&self) -> impl Iterator<Item = ItemReference> + 'a {
+        let id = self.id();
+        self.all_references().filter(move |r| r.from_item_id == id)
+    }
+
+    /// References from other items to this item, e.g. `cdsc` from Exif to the image it describes
+    pub fn referenced_by(&self) -> impl Iterator<Item = ItemReference> + 'a {
+        let id = self.id();
+        self.all_references().filter(move |r| r.to_item_id == id)
+    }
+
+    /// Properties from `ipco` associated with this item. Properties that this crate can't parse are omitted.
+    pub fn properties(&self) -> impl Iterator<Item = Property<'a>> + 'a {
+        let id = self.id();
+        self.meta.properties.iter()
+            .filter(move |p| p.item_id == id)
+            .filter_map(|p| Some(match &p.property {
+                ItemProperty::Channels(bits) => Property::PixelInformation(bits),
+                ItemProperty::AuxiliaryType(aux) => {
+                    let (aux_type, subtype) = aux.type_subtype();
+                    Property::AuxiliaryType { aux_type, subtype }
+                },
+                ItemProperty::ContentLightLevel(cll) => Property::ContentLightLevel(*cll),
+                ItemProperty::MasteringDisplayColourVolume(mdcv) => Property::MasteringDisplayColourVolume(*mdcv),
+                ItemProperty::ImageSpatialExtents(ispe) => Property::ImageSpatialExtents(*ispe),
+                ItemProperty::Unsupported => return None,
+            }))
+    }
+
+    fn all_references(&self) -> impl Iterator<Item = ItemReference> + 'a {
+        self.meta.item_references.iter().map(|r| ItemReference {
+            reference_type: r.item_type.value,
+            from_item_id: r.from_item_id,
+            to_item_id: r.to_item_id,
+        })
+    }
+}
+
+impl core::fmt::Debug for ItemInfo<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ItemInfo")
+            .field("id", &self.id())
+            .field("item_type", &self.info.item_type)
+            .field("hidden", &self.is_hidden())
+            .field("protected", &self.is_protected())
+            .field("construction_method", &self.construction_method())
+            .finish_non_exhaustive()
+    }
+}
+
+/// See [`ItemInfo::extents()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemExtent {
+    pub start: u64,
+    /// Exclusive. `None` if the extent continues until the end of its box.
+    pub end: Option<u64>,
+}
+
+impl From<&ExtentRange> for ItemExtent {
+    fn from(range: &ExtentRange) -> Self {
+        match range {
+            ExtentRange::WithLength(range) => Self { start: range.start, end: Some(range.end) },
+            ExtentRange::ToEnd(range) => Self { start: range.start, end: None },
+        }
+    }
+}
+
+/// Entry of the `iref` box
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemReference {
+    /// `auxl`, `cdsc`, `thmb`, `dimg`, etc.
+    pub reference_type: [u8; 4],
+    /// The item that refers, e.g. the alpha item of an `auxl` or the Exif item of a `cdsc` reference
+    pub from_item_id: u32,
+    /// The item referred to, e.g. the primary item the alpha or Exif item describes
+    pub to_item_id: u32,
+}
+
+/// See [`ItemInfo::properties()`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum Property<'a> {
+    /// `pixi`, bits per channel
+    PixelInformation(&'a [u8]),
+    /// `auxC`, e.g. `urn:mpeg:mpegB:cicp:systems:auxiliary:alpha`
+    AuxiliaryType { aux_type: &'a [u8], subtype: &'a [u8] },
+    /// `clli`
+    ContentLightLevel(ContentLightLevel),
+    /// `mdcv`
+    MasteringDisplayColourVolume(MasteringDisplayColourVolume),
+    /// `ispe`
+    ImageSpatialExtents(ImageSpatialExtents),
+}
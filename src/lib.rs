@@ -1,27 +1,53 @@
 #![allow(clippy::missing_safety_doc)]
+#![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
 //! Module for parsing ISO Base Media Format aka video/mp4 streams.
 
 // This Source Code Form is subject to the terms of the Mozilla Public
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+extern crate alloc;
+
 use arrayvec::ArrayVec;
 use log::{debug, warn};
 
 use bitreader::BitReader;
-use byteorder::ReadBytesExt;
 use fallible_collections::{TryClone, TryReserveError};
-use std::convert::{TryFrom, TryInto as _};
+use core::convert::{TryFrom, TryInto as _};
 
-use std::io::{BufRead, Read, Take};
-use std::num::NonZeroU32;
-use std::ops::{Range, RangeFrom};
+use alloc::borrow::Cow;
+use core::num::NonZeroU32;
+use core::ops::{Range, RangeFrom};
+#[cfg(feature = "std")]
+use std::io::{BufRead, Seek, SeekFrom};
 
 mod obu;
+pub use crate::obu::{annexb_to_low_overhead, is_annexb, low_overhead_to_annexb, ItuTT35Metadata, Obu, ObuExtension, ObuIter, ObuMetadata, ObuType, ScalabilityMetadata, ScalabilityStructure, SpatialLayer, StillImageViolation, TemporalGroupEntry};
+
+mod push;
+pub use crate::push::{AvifPushParser, PushEvent};
+
+#[cfg(feature = "async")]
+mod async_reader;
+#[cfg(feature = "async")]
+pub use crate::async_reader::{read_avif_async, read_avif_async_with_options};
 
 mod boxes;
 use crate::boxes::{BoxType, FourCC};
 
+mod items;
+pub use crate::items::{ItemExtent, ItemInfo, ItemReference, Property};
+
+mod probe;
+pub use crate::probe::{probe, FileFormat, Probe};
+
+mod dump;
+pub use crate::dump::{dump_boxes, BoxFields, BoxNode, BoxTree, ItemLocation, PropertyAssociation};
+
+mod source;
+pub use crate::source::ByteSource;
+use crate::source::{read_input, ByteSourceExt, Input, Take};
+
 /// This crate can be used from C.
 #[cfg(feature = "c_api")]
 pub mod c_api;
@@ -40,7 +66,7 @@ trait ToU64 {
 /// which can fail `TryInto<usize>` is used, it may panic.
 impl ToU64 for usize {
     fn to_u64(self) -> u64 {
-        const _: () = assert!(std::mem::size_of::<usize>() <= std::mem::size_of::<u64>());
+        const _: () = assert!(core::mem::size_of::<usize>() <= core::mem::size_of::<u64>());
         self.try_into().ok().unwrap()
     }
 }
@@ -58,7 +84,7 @@ macro_rules! impl_to_usize_from {
     ( $from_type:ty ) => {
         impl ToUsize for $from_type {
             fn to_usize(self) -> usize {
-                const _: () = assert!(std::mem::size_of::<$from_type>() <= std::mem::size_of::<usize>());
+                const _: () = assert!(core::mem::size_of::<$from_type>() <= core::mem::size_of::<usize>());
                 self.try_into().ok().unwrap()
             }
         }
@@ -81,13 +107,40 @@ impl<T> OffsetReader<T> {
     }
 }
 
-impl<T: Read> Read for OffsetReader<T> {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        let bytes_read = self.reader.read(buf)?;
+#[cfg(feature = "std")]
+impl<T: Seek> OffsetReader<T> {
+    /// Seeks relative to the current position, because the reader doesn't have to start at the beginning of the stream
+    fn seek_to(&mut self, offset: u64) -> Result<()> {
+        let delta = if offset >= self.offset {
+            i64::try_from(offset - self.offset)?
+        } else {
+            -i64::try_from(self.offset - offset)?
+        };
+        self.reader.seek(SeekFrom::Current(delta))?;
+        self.offset = offset;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Input + Seek> OffsetReader<T> {
+    /// Checks whether the file ends before `end`, by reading the byte before it
+    fn is_truncated_at(&mut self, end: u64) -> Result<bool> {
+        let Some(last) = end.checked_sub(1) else {
+            return Ok(false);
+        };
+        self.seek_to(last)?;
+        Ok(self.read_bytes(&mut [0])? == 0)
+    }
+}
+
+impl<T: Input> ByteSource for OffsetReader<T> {
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let bytes_read = read_input(&mut self.reader, buf)?;
         self.offset = self
             .offset
             .checked_add(bytes_read.to_u64())
-            .ok_or(Error::Unsupported("total bytes read too large for offset type"))?;
+            .ok_or(Error::Unsupported(UnsupportedFeature::Other("total bytes read too large for offset type")))?;
         Ok(bytes_read)
     }
 }
@@ -110,26 +163,108 @@ struct String;
 /// This enum wraps the standard `io::Error` type, unified with
 /// our own parser error states and those of crates we use.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// Parse error caused by corrupt or malformed data.
     InvalidData(&'static str),
     /// Parse error caused by limited parser support rather than invalid data.
-    Unsupported(&'static str),
+    Unsupported(UnsupportedFeature),
     /// Reflect `std::io::ErrorKind::UnexpectedEof` for short data.
     UnexpectedEOF,
     /// Propagate underlying errors from `std::io`.
+    #[cfg(feature = "std")]
     Io(std::io::Error),
     /// `read_mp4` terminated without detecting a moov box.
     NoMoov,
     /// Out of memory
     OutOfMemory,
+    /// A limit set in [`ParseOptions`] has been exceeded
+    LimitExceeded(&'static str),
+}
+
+/// See [`Error::Unsupported`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UnsupportedFeature {
+    /// Animated AVIF (`avis` brand). It can be decoded as an AV1 video instead.
+    Animation,
+    /// The primary item is derived from other items, e.g. a `grid` of tiles
+    DerivedImage([u8; 4]),
+    /// Item data is in another item (`item_offset`, 2). Found when parsing `iloc`, so every reader rejects it.
+    ///
+    /// Items in `idat` (1) are read by all readers.
+    ConstructionMethod(u8),
+    /// Item is encrypted (`item_protection_index` is set)
+    Protection,
+    /// Item data is in another file (`data_reference_index` is set)
+    ExternalReference,
+    /// The box has a newer version than the parser knows
+    BoxVersion { box_type: [u8; 4], version: u8 },
+    /// Other limitations of the parser
+    Other(&'static str),
+}
+
+impl core::fmt::Display for UnsupportedFeature {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::Animation => f.write_str("Animated AVIF is not supported. Please use real AV1 videos instead."),
+            Self::DerivedImage(item_type) if item_type == *b"grid" => f.write_str("Grid-based AVIF collage is not supported"),
+            Self::DerivedImage(item_type) => write!(f, "derived image '{}' is not supported", FourCC::from(item_type)),
+            Self::ConstructionMethod(2) => f.write_str("construction_method 'item_offset' is not supported"),
+            Self::ConstructionMethod(method) => write!(f, "unsupported construction_method {method}"),
+            Self::Protection => f.write_str("protected items (infe.item_protection_index != 0) are not supported"),
+            Self::ExternalReference => f.write_str("external file references (iloc.data_reference_index != 0) are not supported"),
+            Self::BoxVersion { box_type, version } => write!(f, "unsupported version {version} of '{}' box", FourCC::from(box_type)),
+            Self::Other(s) => f.write_str(s),
+        }
+    }
+}
+
+/// An [`Error`] with the location of the box it has been found in
+///
+/// Returned by the readers that take [`ParseOptions`], such as [`read_avif_with_options()`]. The other readers return only the [`Error`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ErrorContext {
+    /// What went wrong, for matching on the kind of error
+    pub error: Error,
+    /// The innermost box the error is in. Empty for errors outside of boxes, such as in item data.
+    pub box_path: BoxPath,
+    /// Offset of the box from the beginning of the file
+    pub offset: u64,
 }
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} in '{}' at {}", self.error, self.box_path, self.offset)
+    }
+}
+
+impl core::error::Error for ErrorContext {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<Error> for ErrorContext {
+    fn from(error: Error) -> Self {
+        Self { error, box_path: BoxPath::default(), offset: 0 }
+    }
+}
+
+impl From<ErrorContext> for Error {
+    fn from(context: ErrorContext) -> Self {
+        context.error
+    }
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let msg = match self {
-            Self::InvalidData(s) | Self::Unsupported(s) => s,
+            Self::InvalidData(s) | Self::LimitExceeded(s) => s,
+            Self::Unsupported(feature) => return feature.fmt(f),
             Self::UnexpectedEOF => "EOF",
+            #[cfg(feature = "std")]
             Self::Io(err) => return err.fmt(f),
             Self::NoMoov => "Missing Moov box",
             Self::OutOfMemory => "OOM",
@@ -138,7 +273,15 @@ impl std::fmt::Display for Error {
     }
 }
 
-impl std::error::Error for Error {}
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
 
 impl From<bitreader::BitReaderError> for Error {
     #[cold]
@@ -150,6 +293,7 @@ impl From<bitreader::BitReaderError> for Error {
     }
 }
 
+#[cfg(feature = "std")]
 impl From<std::io::Error> for Error {
     fn from(err: std::io::Error) -> Self {
         match err.kind() {
@@ -159,18 +303,19 @@ impl From<std::io::Error> for Error {
     }
 }
 
-impl From<std::string::FromUtf8Error> for Error {
-    fn from(_: std::string::FromUtf8Error) -> Self {
+impl From<alloc::string::FromUtf8Error> for Error {
+    fn from(_: alloc::string::FromUtf8Error) -> Self {
         Self::InvalidData("invalid utf8")
     }
 }
 
-impl From<std::num::TryFromIntError> for Error {
-    fn from(_: std::num::TryFromIntError) -> Self {
-        Self::Unsupported("integer conversion failed")
+impl From<core::num::TryFromIntError> for Error {
+    fn from(_: core::num::TryFromIntError) -> Self {
+        Self::Unsupported(UnsupportedFeature::Other("integer conversion failed"))
     }
 }
 
+#[cfg(feature = "std")]
 impl From<Error> for std::io::Error {
     fn from(err: Error) -> Self {
         let kind = match err {
@@ -190,7 +335,7 @@ impl From<TryReserveError> for Error {
 }
 
 /// Result shorthand using our Error enum.
-pub type Result<T, E = Error> = std::result::Result<T, E>;
+pub type Result<T, E = Error> = core::result::Result<T, E>;
 
 /// Basic ISO box structure.
 ///
@@ -217,15 +362,6 @@ impl BoxHeader {
     const MIN_LARGE_SIZE: u64 = 16;
 }
 
-/// File type box 'ftyp'.
-#[derive(Debug)]
-#[allow(unused)]
-struct FileTypeBox {
-    major_brand: FourCC,
-    minor_version: u32,
-    compatible_brands: TryVec<FourCC>,
-}
-
 // Handler reference box 'hdlr'
 #[derive(Debug)]
 #[allow(unused)]
@@ -281,44 +417,319 @@ pub struct MasteringDisplayColourVolume {
     pub min_luminance: u32,
 }
 
+/// Image width and height from the `ispe` property.
+///
+/// See ISO 23008-12:2017 § 6.5.3
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageSpatialExtents {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Encryption of a protected item, from a `sinf` box in `ipro`.
+///
+/// See [`ItemInfo::protection()`] and ISO 23001-7 (Common Encryption)
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub struct ProtectionScheme {
+    /// Type of the item before encryption, from the `frma` box
+    pub original_format: Option<[u8; 4]>,
+    /// `cenc`, `cbcs`, etc. from the `schm` box
+    pub scheme_type: [u8; 4],
+    /// `0x0001_0000` for version 1.0
+    pub scheme_version: u32,
+    /// Default KID from the `tenc` box, if present
+    pub key_id: Option<[u8; 16]>,
+    /// 0, 8 or 16. When 0, `constant_iv` is used.
+    pub per_sample_iv_size: u8,
+    /// Empty if IVs are per sample
+    pub constant_iv: TryVec<u8>,
+    /// Number of encrypted and skipped 16-byte blocks of the pattern encryption (`cens` and `cbcs`)
+    pub crypt_byte_block: u8,
+    pub skip_byte_block: u8,
+}
+
+/// Brands from the `ftyp` box, see [`AvifData::file_type`]
+///
+/// See ISO 14496-12:2015 § 4.3
+#[derive(Debug, Default, PartialEq)]
+#[non_exhaustive]
+pub struct FileType {
+    /// `avif` for AVIF images
+    pub major_brand: [u8; 4],
+    pub minor_version: u32,
+    /// Usually includes `mif1`, `miaf` and `avif`
+    pub compatible_brands: TryVec<[u8; 4]>,
+}
+
+impl FileType {
+    /// Why the file has been accepted as AVIF. `None` if it doesn't have the `avif` brand.
+    #[must_use]
+    pub fn avif_brand(&self) -> Option<AvifBrand> {
+        if self.major_brand == *b"avif" {
+            Some(AvifBrand::Major)
+        } else if self.compatible_brands.contains(b"avif") {
+            Some(AvifBrand::Compatible)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the brand is either the major or a compatible brand
+    #[must_use]
+    pub fn has_brand(&self, brand: [u8; 4]) -> bool {
+        self.major_brand == brand || self.compatible_brands.contains(&brand)
+    }
+
+    /// `miaf` brand, see ISO 23000-22
+    #[must_use]
+    pub fn is_miaf(&self) -> bool {
+        self.has_brand(*b"miaf")
+    }
+
+    /// The most restrictive AVIF profile the file claims to conform to (`MA1B` or `MA1A` brand)
+    #[must_use]
+    pub fn avif_profile(&self) -> Option<AvifProfile> {
+        [AvifProfile::Baseline, AvifProfile::Advanced].into_iter().find(|p| self.has_brand(p.brand()))
+    }
+
+    /// `avio` brand: image sequences use only intra-coded frames
+    #[must_use]
+    pub fn is_intra_only(&self) -> bool {
+        self.has_brand(*b"avio")
+    }
+}
+
+/// See [`FileType::avif_brand()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AvifBrand {
+    /// `avif` is the major brand
+    Major,
+    /// The major brand is something else, such as `mif1` or `miaf`, and `avif` is in `compatible_brands`
+    Compatible,
+}
+
+/// AVIF profiles, see AVIF spec § 7
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AvifProfile {
+    /// `MA1B`: AV1 Main profile, level 5.1 or lower
+    Baseline,
+    /// `MA1A`: AV1 High profile, level 6.0 or lower
+    Advanced,
+}
+
+impl AvifProfile {
+    /// Brand of the profile in `ftyp`
+    #[must_use]
+    pub fn brand(self) -> [u8; 4] {
+        match self {
+            Self::Baseline => *b"MA1B",
+            Self::Advanced => *b"MA1A",
+        }
+    }
+
+    /// Highest allowed AV1 `seq_profile`
+    #[must_use]
+    pub fn max_seq_profile(self) -> u8 {
+        match self {
+            Self::Baseline => 0,
+            Self::Advanced => 1,
+        }
+    }
+
+    /// Highest allowed AV1 `seq_level_idx`. 13 is level 5.1, and 16 is level 6.0.
+    #[must_use]
+    pub fn max_seq_level_idx(self) -> u8 {
+        match self {
+            Self::Baseline => 13,
+            Self::Advanced => 16,
+        }
+    }
+
+    /// `MaxPicSize`, `MaxHSize` and `MaxVSize` of the highest allowed level, see AV1 spec Annex A.3
+    fn max_frame_size(self) -> (u64, u32, u32) {
+        match self {
+            Self::Baseline => (8_912_896, 8192, 4352),
+            Self::Advanced => (35_651_584, 16384, 8704),
+        }
+    }
+}
+
+/// A deviation of the file from the limits of the profile it claims, see [`AvifData::check_profile()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProfileViolation {
+    /// AV1 `seq_profile` is higher than the AVIF profile allows
+    SeqProfile { profile: AvifProfile, seq_profile: u8 },
+    /// AV1 `seq_level_idx` is higher than the AVIF profile allows
+    Level { profile: AvifProfile, seq_level_idx: u8 },
+    /// Frame is larger than the highest level of the AVIF profile allows
+    FrameSize { profile: AvifProfile, width: u32, height: u32 },
+    /// MIAF requires the `ispe` property
+    MissingSpatialExtents,
+}
+
+impl core::fmt::Display for ProfileViolation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::SeqProfile { profile, seq_profile } => write!(f, "seq_profile {seq_profile} is not allowed in {profile:?} profile"),
+            Self::Level { profile, seq_level_idx } => write!(f, "seq_level_idx {seq_level_idx} is not allowed in {profile:?} profile"),
+            Self::FrameSize { profile, width, height } => write!(f, "{width}x{height} is too large for {profile:?} profile"),
+            Self::MissingSpatialExtents => f.write_str("MIAF requires ispe property"),
+        }
+    }
+}
+
+/// See [`AvifData::check_profile()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProfileDiagnostic {
+    pub item: ItemRole,
+    pub violation: ProfileViolation,
+}
+
 /// Buffered AV1 data
+///
+/// By default item data is copied into `TryVec`s. See [`read_avif_slice()`] for a variant that borrows from the input.
 #[derive(Debug, Default)]
 #[non_exhaustive]
-pub struct AvifData {
+pub struct AvifData<Item = TryVec<u8>> {
     /// AV1 data for the color channels.
     ///
     /// The collected data indicated by the `pitm` box, See ISO 14496-12:2015 § 8.11.4
-    pub primary_item: TryVec<u8>,
+    pub primary_item: Item,
     /// AV1 data for alpha channel.
     ///
     /// Associated alpha channel for the primary item, if any
-    pub alpha_item: Option<TryVec<u8>>,
+    pub alpha_item: Option<Item>,
     /// If true, divide RGB values by the alpha value.
     ///
     /// See `prem` in MIAF § 7.3.5.2
     pub premultiplied_alpha: bool,
-    /// Content light level from the container's `clli` property, if present.
+    /// Content light level from the container's `clli` property, or else from the primary item's AV1 metadata OBUs.
     pub content_light_level: Option<ContentLightLevel>,
-    /// Mastering display colour volume from the container's `mdcv` property, if present.
+    /// Mastering display colour volume from the container's `mdcv` property, or else from the primary item's AV1 metadata OBUs.
     pub mastering_display: Option<MasteringDisplayColourVolume>,
+    /// Dimensions from the primary item's `ispe` property, if present.
+    pub primary_spatial_extents: Option<ImageSpatialExtents>,
+    /// Dimensions from the alpha item's `ispe` property, if present.
+    pub alpha_spatial_extents: Option<ImageSpatialExtents>,
+    /// Non-fatal issues found in the file
+    pub warnings: TryVec<ParseWarning>,
+    /// `(item_id, data)` of other items selected with [`AvifHeader::load_metadata()`], [`AvifHeader::load_item()`], etc.
+    pub other_items: TryVec<(u32, Item)>,
+    /// Brands from the `ftyp` box
+    pub file_type: FileType,
 }
 
+/// [`AvifData`] borrowing item data from the input slice
+pub type AvifDataRef<'a> = AvifData<Cow<'a, [u8]>>;
+
 impl AvifData {
-    /// It can be used with `&mut &[u8]`. Otherwise it's best to use [`BufReader`](std::io::BufReader).
-    pub fn from_reader<R: Read>(reader: &mut R) -> Result<Self> {
+    /// It can be used with `&mut &[u8]`. Otherwise it's best to use `BufReader`.
+    pub fn from_reader<R: Input>(reader: &mut R) -> Result<Self> {
         read_avif(reader)
     }
+}
 
+impl<'a> AvifDataRef<'a> {
+    /// See [`read_avif_slice()`]
+    pub fn from_slice(data: &'a [u8]) -> Result<Self> {
+        read_avif_slice(data)
+    }
+}
+
+impl<Item: core::ops::Deref<Target = [u8]>> AvifData<Item> {
     /// Parses AV1 data to get basic properties of the opaque channel
+    ///
+    /// The result isn't cached. Every call walks the OBUs of the item again, so keep the result if it's needed more than once.
     pub fn primary_item_metadata(&self) -> Result<AV1Metadata> {
         AV1Metadata::parse_av1_bitstream(&self.primary_item)
     }
 
     /// Parses AV1 data to get basic properties about the alpha channel, if any
+    ///
+    /// Like [`AvifData::primary_item_metadata()`], it parses the item on every call.
     pub fn alpha_item_metadata(&self) -> Result<Option<AV1Metadata>> {
         self.alpha_item.as_deref().map(AV1Metadata::parse_av1_bitstream).transpose()
     }
 
+    /// Checks the primary and alpha AV1 items against AVIF's still image constraints.
+    ///
+    /// This is opt-in, and not done when reading the file. An empty list means the items are compliant.
+    pub fn validate_still_image(&self) -> Result<TryVec<StillImageDiagnostic>> {
+        let mut diagnostics = TryVec::new();
+        let items = [(ItemRole::Primary, Some(&self.primary_item)), (ItemRole::Alpha, self.alpha_item.as_ref())];
+        for (item, data) in items {
+            let Some(data) = data else { continue };
+            for violation in obu::still_image_violations(data)? {
+                diagnostics.push(StillImageDiagnostic { item, violation })?;
+            }
+        }
+        Ok(diagnostics)
+    }
+
+    /// Checks that the alpha item can be composited with the primary item.
+    ///
+    /// Compares dimensions and bit depth in both AV1 sequence headers, and `ispe` properties when present.
+    /// Returns an empty list if there's no alpha or it matches.
+    pub fn check_alpha_geometry(&self) -> Result<TryVec<AlphaGeometryIssue>> {
+        let mut issues = TryVec::new();
+        let Some(alpha) = self.alpha_item_metadata()? else {
+            return Ok(issues);
+        };
+        let color = self.primary_item_metadata()?;
+
+        let color_size = (color.max_frame_width.get(), color.max_frame_height.get());
+        let alpha_size = (alpha.max_frame_width.get(), alpha.max_frame_height.get());
+        if color_size != alpha_size {
+            issues.push(AlphaGeometryIssue::Dimensions { color: color_size, alpha: alpha_size })?;
+        }
+        if let (Some(color), Some(alpha)) = (self.primary_spatial_extents, self.alpha_spatial_extents)
+            && color != alpha
+        {
+            issues.push(AlphaGeometryIssue::SpatialExtents { color, alpha })?;
+        }
+        if color.bit_depth != alpha.bit_depth {
+            issues.push(AlphaGeometryIssue::BitDepth { color: color.bit_depth, alpha: alpha.bit_depth })?;
+        }
+        if !alpha.monochrome {
+            issues.push(AlphaGeometryIssue::NotMonochrome)?;
+        }
+        Ok(issues)
+    }
+
+    /// Checks the primary and alpha items against the limits of the profile in [`AvifData::file_type`], and MIAF requirements.
+    ///
+    /// This is opt-in, and not done when reading the file. An empty list means the file is compliant, or doesn't claim a profile.
+    pub fn check_profile(&self) -> Result<TryVec<ProfileDiagnostic>> {
+        let mut diagnostics = TryVec::new();
+        if self.file_type.is_miaf() && self.primary_spatial_extents.is_none() {
+            diagnostics.push(ProfileDiagnostic { item: ItemRole::Primary, violation: ProfileViolation::MissingSpatialExtents })?;
+        }
+        let Some(profile) = self.file_type.avif_profile() else {
+            return Ok(diagnostics);
+        };
+
+        let items = [(ItemRole::Primary, Some(self.primary_item_metadata()?)), (ItemRole::Alpha, self.alpha_item_metadata()?)];
+        let (max_pic_size, max_width, max_height) = profile.max_frame_size();
+        for (item, meta) in items {
+            let Some(meta) = meta else { continue };
+            let mut push = |violation| diagnostics.push(ProfileDiagnostic { item, violation });
+            if meta.seq_profile > profile.max_seq_profile() {
+                push(ProfileViolation::SeqProfile { profile, seq_profile: meta.seq_profile })?;
+            }
+            if meta.seq_level_idx > profile.max_seq_level_idx() {
+                push(ProfileViolation::Level { profile, seq_level_idx: meta.seq_level_idx })?;
+            }
+            let (width, height) = (meta.max_frame_width.get(), meta.max_frame_height.get());
+            if width > max_width || height > max_height || u64::from(width) * u64::from(height) > max_pic_size {
+                push(ProfileViolation::FrameSize { profile, width, height })?;
+            }
+        }
+        Ok(diagnostics)
+    }
+
     #[must_use]
     pub fn content_light_level(&self) -> Option<ContentLightLevel> {
         self.content_light_level
@@ -328,32 +739,374 @@ impl AvifData {
     pub fn mastering_display(&self) -> Option<MasteringDisplayColourVolume> {
         self.mastering_display
     }
+
+    /// Fills in HDR metadata the container lacks from the primary item's metadata OBUs, once the item has been read
+    fn fill_hdr_from_obus(&mut self) {
+        if self.content_light_level.is_some() && self.mastering_display.is_some() {
+            return;
+        }
+        match obu::parse_metadata_obus(&self.primary_item) {
+            Ok(metadata) => for m in metadata {
+                match m {
+                    ObuMetadata::ContentLightLevel(cll) => { self.content_light_level.get_or_insert(cll); },
+                    ObuMetadata::MasteringDisplay(mdcv) => { self.mastering_display.get_or_insert(mdcv); },
+                    _ => {},
+                }
+            },
+            Err(err) => warn!("AV1 metadata: {err}"),
+        }
+    }
+}
+
+/// Which of the items in [`AvifData`] a diagnostic or a byte range is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ItemRole {
+    Primary,
+    Alpha,
+    /// Item describing the primary item via a `cdsc` reference, such as Exif or XMP
+    Metadata,
+    /// Thumbnail of the primary item, via a `thmb` reference
+    Thumbnail,
+    /// Auxiliary image of the primary item other than alpha, e.g. a depth map
+    Auxiliary,
+    /// Item selected with [`AvifHeader::load_item()`] that has none of the other roles
+    Other,
+}
+
+/// Location of item data in the file, see [`AvifHeader::item_byte_ranges()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemByteRange {
+    pub item: ItemRole,
+    pub item_id: u32,
+    /// `av01`, `Exif`, `mime`, etc.
+    pub item_type: [u8; 4],
+    /// Offset from the beginning of the file
+    pub start: u64,
+    /// Exclusive. `None` if the data extends to the end of its `mdat` box, which isn't known yet.
+    /// Fetch it until the end of the file (e.g. `Range: bytes=start-`).
+    pub end: Option<u64>,
+}
+
+/// See [`AvifData::validate_still_image()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StillImageDiagnostic {
+    pub item: ItemRole,
+    pub violation: StillImageViolation,
+}
+
+/// See [`AvifData::check_alpha_geometry()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AlphaGeometryIssue {
+    /// `max_frame_width`/`max_frame_height` of the sequence headers differ
+    Dimensions { color: (u32, u32), alpha: (u32, u32) },
+    /// `ispe` properties of the items differ
+    SpatialExtents { color: ImageSpatialExtents, alpha: ImageSpatialExtents },
+    /// AVIF requires alpha to have the same bit depth as the color item, but the decoded alpha can be scaled to match
+    BitDepth { color: u8, alpha: u8 },
+    /// AVIF requires alpha to be monochrome, but files from older encoders may have chroma planes in alpha,
+    /// which can be ignored.
+    NotMonochrome,
+}
+
+impl AlphaGeometryIssue {
+    /// `false` for issues that can be worked around
+    #[must_use]
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Self::NotMonochrome | Self::BitDepth { .. })
+    }
+}
+
+impl core::fmt::Display for AlphaGeometryIssue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Dimensions { color, alpha } => write!(f, "alpha is {}x{}, but color is {}x{}", alpha.0, alpha.1, color.0, color.1),
+            Self::SpatialExtents { color, alpha } => write!(f, "alpha ispe is {}x{}, but color ispe is {}x{}", alpha.width, alpha.height, color.width, color.height),
+            Self::BitDepth { color, alpha } => write!(f, "alpha is {alpha}-bit, but color is {color}-bit"),
+            Self::NotMonochrome => f.write_str("alpha is not monochrome"),
+        }
+    }
 }
 
 /// Parse AVIF header
+#[cfg(feature = "std")]
 impl<BufReader: BufRead> AvifHeader<BufReader> {
     /// Can read from a `&mut &[u8]`, `io::Cursor` or [`BufReader`](std::io::BufReader).
     pub fn from_reader(reader: BufReader) -> Result<Self> {
-        read_avif_header(reader)
+        Ok(read_avif_header(reader)?)
+    }
+
+    /// Like [`AvifHeader::from_reader()`], with limits for untrusted files. The limits also apply to [`AvifHeader::read_avif()`].
+    ///
+    /// Errors come with the location of the box they're in.
+    pub fn from_reader_with_options(reader: BufReader, options: &ParseOptions) -> Result<Self, ErrorContext> {
+        read_avif_header_with(reader, None, ParseContext::new(options))
     }
 }
 
-impl<R: Read> AvifHeader<R> {
+#[cfg(feature = "std")]
+impl<R: Input + Seek> AvifHeader<R> {
+    /// Skips over `mdat` boxes instead of buffering them. Use [`AvifHeader::read_avif_seek()`] to read the items afterwards.
+    ///
+    /// Wrap files in a [`BufReader`](std::io::BufReader).
+    pub fn from_seekable_reader(reader: R) -> Result<Self> {
+        Ok(read_avif_header_with(reader, Some(OffsetReader::seek_to), ParseContext::default())?)
+    }
+
+    /// Like [`AvifHeader::from_seekable_reader()`], with limits for untrusted files. The limits also apply to [`AvifHeader::read_avif_seek()`].
+    ///
+    /// Errors come with the location of the box they're in.
+    pub fn from_seekable_reader_with_options(reader: R, options: &ParseOptions) -> Result<Self, ErrorContext> {
+        read_avif_header_with(reader, Some(OffsetReader::seek_to), ParseContext::new(options))
+    }
+
+    /// Read only the bytes of the primary and alpha items, seeking to each of their extents.
+    ///
+    /// Other data in the file, such as thumbnails or Exif, is skipped unless selected with [`AvifHeader::load_metadata()`], etc.
+    pub fn read_avif_seek(self) -> Result<AvifData> {
+        read_avif_body_seek(self)
+    }
+}
+
+/// Parse AVIF header from a [`ByteSource`]
+#[cfg(not(feature = "std"))]
+impl<R: ByteSource> AvifHeader<R> {
+    pub fn from_reader(reader: R) -> Result<Self> {
+        Ok(read_avif_header(reader)?)
+    }
+
+    /// Like [`AvifHeader::from_reader()`], with limits for untrusted files. The limits also apply to [`AvifHeader::read_avif()`].
+    ///
+    /// Errors come with the location of the box they're in.
+    pub fn from_reader_with_options(reader: R, options: &ParseOptions) -> Result<Self, ErrorContext> {
+        read_avif_header_with(reader, None, ParseContext::new(options))
+    }
+}
+
+impl<R: Input> AvifHeader<R> {
     /// Read the rest of the file
     pub fn read_avif(self) -> Result<AvifData> {
         read_avif_body(self)
     }
+
+    /// Read the rest of the file, and return the data of any item listed in [`AvifHeader::items()`]
+    ///
+    /// Extents in `mdat` and `idat` boxes are concatenated.
+    pub fn read_item(self, item_id: u32) -> Result<TryVec<u8>> {
+        let mut items = read_items_body(self, &[item_id])?;
+        items.pop().ok_or(Error::InvalidData("item not present in iloc"))
+    }
+
+    /// Like [`AvifHeader::read_item()`], but reads several items in one pass. The data is in the same order as `item_ids`.
+    pub fn read_items(self, item_ids: &[u32]) -> Result<TryVec<TryVec<u8>>> {
+        read_items_body(self, item_ids)
+    }
 }
 
+/// Reading from fetched byte ranges, e.g. from HTTP range requests.
+///
+/// Parse only the beginning of the file with [`AvifHeader::from_reader()`], fetch [`AvifHeader::item_byte_ranges()`],
+/// and pass `(start, bytes)` of each fetched range to [`AvifHeader::read_avif_from_ranges()`].
+/// Fetched ranges may be larger than requested, merged together, or split into adjacent ranges.
 impl<R> AvifHeader<R> {
+    /// File byte ranges of the items selected with [`AvifHeader::load_alpha()`], [`AvifHeader::load_metadata()`], etc., sorted by offset.
+    ///
+    /// Data that has already been read together with the header isn't included.
+    pub fn item_byte_ranges(&self) -> Result<TryVec<ItemByteRange>> {
+        let item_ids = self.selected_item_ids()?;
+        let mut ranges = TryVec::new();
+        for loc in self.meta.iloc_items.iter().filter(|loc| item_ids.contains(&loc.item_id)) {
+            let item = self.item_role(loc.item_id);
+            // `idat` has been read with the header
+            if loc.construction_method == ConstructionMethod::Idat {
+                continue;
+            }
+            if loc.construction_method != ConstructionMethod::File {
+                return Err(Error::Unsupported(UnsupportedFeature::ConstructionMethod(loc.construction_method as u8)));
+            }
+            let item_type = self.meta.item_infos.iter()
+                .find(|info| info.item_id == loc.item_id)
+                .map(|info| info.item_type.value)
+                .unwrap_or_default();
+            for extent in &loc.extents {
+                let range = &extent.extent_range;
+                if self.mdats.iter().any(|mdat| mdat.contains_extent(range)) {
+                    continue;
+                }
+                let end = match range {
+                    ExtentRange::WithLength(range) => Some(range.end),
+                    ExtentRange::ToEnd(_) => self.skipped_mdats.iter()
+                        .find(|mdat| mdat.contains(&range.start()))
+                        .map(|mdat| mdat.end),
+                };
+                ranges.push(ItemByteRange { item, item_id: loc.item_id, item_type, start: range.start(), end })?;
+            }
+        }
+        ranges.sort_unstable_by_key(|r| r.start);
+        Ok(ranges)
+    }
+
+    /// Assemble the selected items from fetched ranges of the file, like [`AvifHeader::read_avif()`]
+    pub fn read_avif_from_ranges(mut self, fetched: &[(u64, &[u8])]) -> Result<AvifData> {
+        let item_ids = self.selected_item_ids()?;
+        self.meta.check_not_protected(item_ids.iter().copied())?;
+        let mut items = TryVec::with_capacity(item_ids.len())?;
+        for &item_id in &item_ids {
+            let mut item_data = TryVec::new();
+            read_item_from_ranges(&self.meta, &self.mdats, &self.skipped_mdats, item_id, fetched, &mut item_data, &mut self.ctx)?;
+            items.push(item_data)?;
+        }
+        let mut data = self.data;
+        assign_items(&self.meta, &mut data, &item_ids, items)?;
+
+        self.ctx.check_av1_dimensions(&data.primary_item, data.alpha_item.as_deref())?;
+        data.fill_hdr_from_obus();
+        data.warnings = self.ctx.warnings;
+        Ok(data)
+    }
+
+    /// Assemble any item, such as a metadata item from [`AvifHeader::item_byte_ranges()`], from fetched ranges of the file
+    ///
+    /// `max_alloc_size` applies to each call separately, on top of the header's allocations.
+    pub fn read_item_from_ranges(&self, item_id: u32, fetched: &[(u64, &[u8])]) -> Result<TryVec<u8>> {
+        let mut ctx = ParseContext { options: self.ctx.options, allocated: self.ctx.allocated, ..ParseContext::default() };
+        let mut item_data = TryVec::new();
+        read_item_from_ranges(&self.meta, &self.mdats, &self.skipped_mdats, item_id, fetched, &mut item_data, &mut ctx)?;
+        Ok(item_data)
+    }
+
+    /// Non-fatal issues found in the header. They're also included in the final [`AvifData`].
+    #[must_use]
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.ctx.warnings
+    }
+
+    fn item_role(&self, item_id: u32) -> ItemRole {
+        let references_primary = |reference_type: &[u8; 4]| self.meta.item_references.iter().any(|iref| {
+            iref.from_item_id == item_id && iref.to_item_id == self.meta.primary_item_id && iref.item_type == reference_type
+        });
+        if item_id == self.meta.primary_item_id {
+            ItemRole::Primary
+        } else if Some(item_id) == self.meta.alpha_item_id {
+            ItemRole::Alpha
+        } else if references_primary(b"cdsc") {
+            ItemRole::Metadata
+        } else if references_primary(b"thmb") {
+            ItemRole::Thumbnail
+        } else if references_primary(b"auxl") {
+            ItemRole::Auxiliary
+        } else {
+            ItemRole::Other
+        }
+    }
+}
+
+/// Choosing which items [`AvifHeader::read_avif()`] loads. Extents of other items are skipped without buffering them,
+/// except `mdat` boxes that precede the `meta` box.
+///
+/// ```rust,no_run
+/// # fn f(file: &[u8]) -> avif_parse::Result<()> {
+/// let avif = avif_parse::AvifHeader::from_reader(file)?.load_alpha(false).read_avif()?;
+/// # Ok(()) }
+/// ```
+impl<R> AvifHeader<R> {
+    /// Load [`AvifData::primary_item`]. Enabled by default.
+    #[must_use]
+    pub fn load_primary(mut self, load: bool) -> Self {
+        self.selection.primary = load;
+        self
+    }
+
+    /// Load [`AvifData::alpha_item`]. Enabled by default.
+    #[must_use]
+    pub fn load_alpha(mut self, load: bool) -> Self {
+        self.selection.alpha = load;
+        self
+    }
+
+    /// Load auxiliary images of the primary item other than alpha, such as depth maps, into [`AvifData::other_items`]
+    #[must_use]
+    pub fn load_auxiliary(mut self, load: bool) -> Self {
+        self.selection.auxiliary = load;
+        self
+    }
+
+    /// Load thumbnails of the primary item into [`AvifData::other_items`]
+    #[must_use]
+    pub fn load_thumbnails(mut self, load: bool) -> Self {
+        self.selection.thumbnails = load;
+        self
+    }
+
+    /// Load Exif, XMP and other metadata items describing the primary item into [`AvifData::other_items`]
+    #[must_use]
+    pub fn load_metadata(mut self, load: bool) -> Self {
+        self.selection.metadata = load;
+        self
+    }
+
+    /// Also load the item with this ID, see [`AvifHeader::items()`]. Fails if the item is not in the `iloc` box.
+    pub fn load_item(mut self, item_id: u32) -> Result<Self> {
+        if !self.meta.iloc_items.iter().any(|loc| loc.item_id == item_id) {
+            return Err(Error::InvalidData("item not present in iloc"));
+        }
+        self.selection.item_ids.push(item_id)?;
+        Ok(self)
+    }
+
+    /// Primary and alpha first, then other items in `iloc` order
+    fn selected_item_ids(&self) -> Result<TryVec<u32>> {
+        let meta = &self.meta;
+        let sel = &self.selection;
+        let references_primary = |item_id: u32, reference_type: &[u8; 4]| meta.item_references.iter().any(|iref| {
+            iref.from_item_id == item_id && iref.to_item_id == meta.primary_item_id && iref.item_type == reference_type
+        });
+
+        let mut item_ids = TryVec::new();
+        let mut others = TryVec::new();
+        for loc in meta.iloc_items.iter() {
+            let id = loc.item_id;
+            if id == meta.primary_item_id {
+                if sel.primary || sel.item_ids.contains(&id) {
+                    item_ids.push(id)?;
+                }
+            } else if Some(id) == meta.alpha_item_id {
+                if sel.alpha || sel.item_ids.contains(&id) {
+                    item_ids.push(id)?;
+                }
+            } else if sel.item_ids.contains(&id)
+                || (sel.auxiliary && references_primary(id, b"auxl"))
+                || (sel.thumbnails && references_primary(id, b"thmb"))
+                || (sel.metadata && references_primary(id, b"cdsc"))
+            {
+                others.push(id)?;
+            }
+        }
+        // primary before alpha, regardless of the iloc order
+        item_ids.sort_unstable_by_key(|&id| id != meta.primary_item_id);
+        item_ids.extend_from_slice(&others)?;
+        Ok(item_ids)
+    }
+}
+
+impl<R> AvifHeader<R> {
+    /// Brands from the `ftyp` box
+    #[must_use]
+    pub fn file_type(&self) -> &FileType {
+        &self.data.file_type
+    }
+
     pub fn has_alpha(&self) -> bool {
         self.meta.alpha_item_id.is_some()
     }
 
+    /// From the container's `clli` property only, since the items haven't been read yet
     pub fn content_light_level(&self) -> Option<ContentLightLevel> {
         self.data.content_light_level()
     }
 
+    /// From the container's `mdcv` property only, since the items haven't been read yet
     pub fn mastering_display(&self) -> Option<MasteringDisplayColourVolume> {
         self.data.mastering_display()
     }
@@ -371,48 +1124,100 @@ pub struct AV1Metadata {
     pub bit_depth: u8,
     /// 0, 1 or 2 for the level of complexity
     pub seq_profile: u8,
+    /// Level of the first operating point. 31 means unconstrained.
+    pub seq_level_idx: u8,
     /// Horizontal and vertical. `false` is full-res.
     pub chroma_subsampling: (bool, bool),
     pub monochrome: bool,
+    /// From the first `METADATA_TYPE_HDR_CLL` OBU, if any
+    pub content_light_level: Option<ContentLightLevel>,
+    /// From the first `METADATA_TYPE_HDR_MDCV` OBU, if any
+    pub mastering_display: Option<MasteringDisplayColourVolume>,
 }
 
 impl AV1Metadata {
     /// Parses raw AV1 bitstream (OBU sequence header) only.
     ///
     /// This is for the bare image payload from an encoder, not an AVIF/HEIF file.
+    /// Both the low-overhead and the Annex B (length-delimited) formats are supported.
     /// To parse AVIF files, see [`AvifHeader::from_reader()`].
     #[inline(never)]
     pub fn parse_av1_bitstream(obu_bitstream: &[u8]) -> Result<Self> {
         let h = obu::parse_obu(obu_bitstream)?;
+
+        let mut content_light_level = None;
+        let mut mastering_display = None;
+        match obu::parse_metadata_obus(obu_bitstream) {
+            Ok(metadata) => for m in metadata {
+                match m {
+                    ObuMetadata::ContentLightLevel(cll) => { content_light_level.get_or_insert(cll); },
+                    ObuMetadata::MasteringDisplay(mdcv) => { mastering_display.get_or_insert(mdcv); },
+                    _ => {},
+                }
+            },
+            Err(err) => warn!("AV1 metadata: {err}"),
+        }
+
         Ok(Self {
             still_picture: h.still_picture,
             max_frame_width: h.max_frame_width,
             max_frame_height: h.max_frame_height,
             bit_depth: h.color.bit_depth,
             seq_profile: h.seq_profile,
+            seq_level_idx: h.seq_level_idx,
             chroma_subsampling: h.color.chroma_subsampling,
             monochrome: h.color.monochrome,
+            content_light_level,
+            mastering_display,
         })
     }
+
+    /// Parses all metadata OBUs (HDR, scalability, ITU-T T.35) in a raw AV1 bitstream, in either format.
+    ///
+    /// Payloads are borrowed from the bitstream.
+    pub fn parse_metadata_obus(obu_bitstream: &[u8]) -> Result<TryVec<ObuMetadata<'_>>> {
+        obu::parse_metadata_obus(obu_bitstream)
+    }
 }
 
 struct AvifInternalMeta {
+    item_infos: TryVec<ItemInfoEntry>,
     item_references: TryVec<SingleItemTypeReferenceBox>,
     properties: TryVec<AssociatedProperty>,
     primary_item_id: u32,
     alpha_item_id: Option<u32>,
     iloc_items: TryVec<ItemLocationBoxItem>,
+    /// Contents of the `idat` box, see `ConstructionMethod::Idat`
+    idat: Option<TryVec<u8>>,
+    /// From the `ipro` box, see `ItemInfoEntry::protection_index`
+    protection_schemes: TryVec<ProtectionScheme>,
+}
+
+impl AvifInternalMeta {
+    /// Protected items can't be decoded, but can be read with `AvifHeader::read_item()`
+    fn check_not_protected(&self, item_ids: impl IntoIterator<Item = u32>) -> Result<()> {
+        for item_id in item_ids {
+            if self.item_infos.iter().any(|info| info.item_id == item_id && info.protection_index != 0) {
+                return Err(Error::Unsupported(UnsupportedFeature::Protection));
+            }
+        }
+        Ok(())
+    }
+
+    fn primary_and_alpha(&self) -> impl Iterator<Item = u32> {
+        core::iter::once(self.primary_item_id).chain(self.alpha_item_id)
+    }
 }
 
 /// A Media Data Box
 /// See ISO 14496-12:2015 § 8.1.1
-struct MediaDataBox {
+struct MediaDataBox<Data = TryVec<u8>> {
     /// Offset of `data` from the beginning of the file. See `ConstructionMethod::File`
     offset: u64,
-    data: TryVec<u8>,
+    data: Data,
 }
 
-impl MediaDataBox {
+impl<Data: core::ops::Deref<Target = [u8]>> MediaDataBox<Data> {
     /// Check whether the beginning of `extent` is within the bounds of the `MediaDataBox`.
     /// We assume extents to not cross box boundaries. If so, this will cause an error
     /// in `read_extent`.
@@ -443,39 +1248,53 @@ impl MediaDataBox {
         }
     }
 
-    /// Copy the range specified by `extent` to the end of `buf` or return an error if the range
+    /// Get the position of `extent` within `data` or return an error if the range
     /// is not fully contained within `MediaDataBox`.
-    fn read_extent(&self, extent: &ExtentRange, buf: &mut TryVec<u8>) -> Result<()> {
+    fn extent_data_range(&self, extent: &ExtentRange) -> Result<Range<usize>> {
         let start_offset = extent
             .start()
             .checked_sub(self.offset)
             .ok_or(Error::InvalidData("mdat does not contain extent"))?;
-        let slice = match extent {
+        let end = match extent {
             ExtentRange::WithLength(range) => {
                 let range_len = range
                     .end
                     .checked_sub(range.start)
                     .ok_or(Error::InvalidData("range start > end"))?;
-                let end = start_offset
+                start_offset
                     .checked_add(range_len)
-                    .ok_or(Error::InvalidData("extent end overflow"))?;
-                self.data.get(start_offset.try_into()?..end.try_into()?)
+                    .ok_or(Error::InvalidData("extent end overflow"))?
             },
-            ExtentRange::ToEnd(_) => self.data.get(start_offset.try_into()?..),
+            ExtentRange::ToEnd(_) => self.data.len().to_u64(),
         };
-        let slice = slice.ok_or(Error::InvalidData("extent crosses box boundary"))?;
-        buf.extend_from_slice(slice)?;
+        if start_offset > end || end > self.data.len().to_u64() {
+            return Err(Error::InvalidData("extent crosses box boundary"));
+        }
+        Ok(start_offset.try_into()?..end.try_into()?)
+    }
+
+    /// Copy the range specified by `extent` to the end of `buf` or return an error if the range
+    /// is not fully contained within `MediaDataBox`.
+    fn read_extent(&self, extent: &ExtentRange, buf: &mut TryVec<u8>) -> Result<()> {
+        buf.extend_from_slice(&self.data[self.extent_data_range(extent)?])?;
         Ok(())
     }
 }
 
 /// Used for 'infe' boxes within 'iinf' boxes
 /// See ISO 14496-12:2015 § 8.11.6
-/// Only versions {2, 3} are supported
+/// Versions 0 and 1 don't have an item type, and are treated as `mime` items
 #[derive(Debug)]
 struct ItemInfoEntry {
     item_id: u32,
     item_type: FourCC,
+    /// Flag bit 0, see ISO 23008-12:2017 § 9.2
+    hidden: bool,
+    name: TryString,
+    /// Only for `mime` items
+    content_type: Option<TryString>,
+    /// 1-based index into `AvifInternalMeta::protection_schemes`, 0 if not protected
+    protection_index: u16,
 }
 
 /// See ISO 14496-12:2015 § 8.11.12
@@ -533,7 +1352,7 @@ impl TryFrom<u8> for IlocVersion {
             0 => Ok(Self::Zero),
             1 => Ok(Self::One),
             2 => Ok(Self::Two),
-            _ => Err(Error::Unsupported("unsupported version in 'iloc' box")),
+            _ => Err(Error::Unsupported(UnsupportedFeature::BoxVersion { box_type: *b"iloc", version: value })),
         }
     }
 }
@@ -550,12 +1369,17 @@ struct ItemLocationBoxItem {
     extents: TryVec<ItemLocationBoxExtent>,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-enum ConstructionMethod {
-    File,
-    Idat,
-    #[allow(dead_code)] // TODO: see https://github.com/mozilla/mp4parse-rust/issues/196
-    Item,
+/// Where the data of an item is stored, see [`ItemInfo::construction_method()`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConstructionMethod {
+    /// In the file, usually in `mdat`
+    File = 0,
+    /// In the `idat` box inside `meta`
+    Idat = 1,
+    /// In another item. Not supported.
+    // TODO: see https://github.com/mozilla/mp4parse-rust/issues/196
+    Item = 2,
 }
 
 /// `extent_index` is omitted since it's only used for `ConstructionMethod::Item` which
@@ -584,15 +1408,16 @@ impl ExtentRange {
 struct BMFFBox<T> {
     head: BoxHeader,
     content: Take<T>,
+    /// Offset of the box header from the beginning of the file
+    start: u64,
+    /// Types of this box and its parents
+    path: BoxPath,
 }
 
-impl<T: Read> BMFFBox<T> {
-    fn read_into_try_vec(&mut self) -> std::io::Result<TryVec<u8>> {
-        let mut vec = std::vec::Vec::new();
-        vec.try_reserve_exact(self.content.limit() as usize)
-            .map_err(|_| std::io::ErrorKind::OutOfMemory)?;
-        self.content.read_to_end(&mut vec)?; // The default impl
-        Ok(vec.into())
+impl<T: ByteSource> BMFFBox<T> {
+    fn read_into_try_vec(&mut self) -> Result<TryVec<u8>> {
+        let limit = self.content.limit();
+        self.content.read_to_try_vec(limit)
     }
 }
 
@@ -601,7 +1426,9 @@ fn box_read_to_end() {
     let tmp = &mut b"1234567890".as_slice();
     let mut src = BMFFBox {
         head: BoxHeader { name: BoxType::FileTypeBox, size: 5, offset: 0 },
-        content: <_ as Read>::take(tmp, 5),
+        content: Take::new(tmp, 5),
+        start: 0,
+        path: BoxPath::default(),
     };
     let buf = src.read_into_try_vec().unwrap();
     assert_eq!(buf.len(), 5);
@@ -613,36 +1440,49 @@ fn box_read_to_end_oom() {
     let tmp = &mut b"1234567890".as_slice();
     let mut src = BMFFBox {
         head: BoxHeader { name: BoxType::FileTypeBox, size: 5, offset: 0 },
-        content: <_ as Read>::take(tmp, usize::MAX.try_into().expect("usize < u64")),
+        content: Take::new(tmp, usize::MAX.try_into().expect("usize < u64")),
+        start: 0,
+        path: BoxPath::default(),
     };
     assert!(src.read_into_try_vec().is_err());
 }
 
 struct BoxIter<T> {
     src: T,
+    /// Offset of the next box from the beginning of the file
+    offset: u64,
+    /// Types of the parent boxes
+    path: BoxPath,
 }
 
-impl<T: Read> BoxIter<T> {
-    fn new(src: T) -> BoxIter<T> {
-        BoxIter { src }
+impl<T: ByteSource> BoxIter<T> {
+    /// Iterates top-level boxes starting at `offset`
+    fn new(src: T, offset: u64) -> BoxIter<T> {
+        BoxIter { src, offset, path: BoxPath::default() }
     }
 
     fn next_box(&mut self) -> Result<Option<BMFFBox<&mut T>>> {
         let r = read_box_header(&mut self.src);
         match r {
-            Ok(h) => Ok(Some(BMFFBox {
-                head: h,
-                content: self.src.by_ref().take(h.size - h.offset),
-            })),
+            Ok(h) => {
+                let start = self.offset;
+                self.offset = start.saturating_add(h.size);
+                Ok(Some(BMFFBox {
+                    head: h,
+                    content: Take::new(&mut self.src, h.size - h.offset),
+                    start,
+                    path: self.path.with(h.name),
+                }))
+            },
             Err(Error::UnexpectedEOF) => Ok(None),
             Err(e) => Err(e),
         }
     }
 }
 
-impl<T: Read> Read for BMFFBox<T> {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        self.content.read(buf)
+impl<T: ByteSource> ByteSource for BMFFBox<T> {
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.content.read_bytes(buf)
     }
 }
 
@@ -650,9 +1490,14 @@ impl<T> BMFFBox<&mut OffsetReader<T>> {
     fn offset(&self) -> u64 {
         self.content.get_ref().offset
     }
+
+    /// Offset just past the end of the box
+    fn end_offset(&self) -> u64 {
+        self.offset().saturating_add(self.content.limit())
+    }
 }
 
-impl<T: Read> BMFFBox<T> {
+impl<T: ByteSource> BMFFBox<T> {
     fn bytes_left(&self) -> u64 {
         self.content.limit()
     }
@@ -662,7 +1507,11 @@ impl<T: Read> BMFFBox<T> {
     }
 
     fn box_iter(&mut self) -> BoxIter<&mut Self> {
-        BoxIter::new(self)
+        BoxIter {
+            offset: self.start.saturating_add(self.head.size.saturating_sub(self.content.limit())),
+            path: self.path.clone(),
+            src: self,
+        }
     }
 }
 
@@ -683,12 +1532,21 @@ impl<T> Drop for BMFFBox<T> {
 /// skip unknown or uninteresting boxes.
 ///
 /// See ISO 14496-12:2015 § 4.2
-fn read_box_header<T: ReadBytesExt>(src: &mut T) -> Result<BoxHeader> {
+fn read_box_header<T: ByteSource>(src: &mut T) -> Result<BoxHeader> {
+    read_box_header_to_end(src, None)
+}
+
+/// Like `read_box_header`, but a box with size 0 is `size_to_end` bytes long
+fn read_box_header_to_end<T: ByteSource>(src: &mut T, size_to_end: Option<u64>) -> Result<BoxHeader> {
     let size32 = be_u32(src)?;
     let name = BoxType::from(be_u32(src)?);
     let size = match size32 {
         // valid only for top-level box and indicates it's the last box in the file.  usually mdat.
-        0 => return Err(Error::Unsupported("unknown sized box")),
+        0 => match size_to_end {
+            Some(size) if size >= BoxHeader::MIN_SIZE => size,
+            Some(_) => return Err(Error::InvalidData("malformed size")),
+            None => return Err(Error::Unsupported(UnsupportedFeature::Other("unknown sized box"))),
+        },
         1 => {
             let size64 = be_u64(src)?;
             if size64 < BoxHeader::MIN_LARGE_SIZE {
@@ -710,7 +1568,7 @@ fn read_box_header<T: ReadBytesExt>(src: &mut T) -> Result<BoxHeader> {
     let _uuid = if name == BoxType::UuidBox {
         if size >= offset + 16 {
             let mut buffer = [0u8; 16];
-            let count = src.read(&mut buffer)?;
+            let count = src.read_bytes(&mut buffer)?;
             offset += count.to_u64();
             if count == 16 {
                 Some(buffer)
@@ -730,7 +1588,7 @@ fn read_box_header<T: ReadBytesExt>(src: &mut T) -> Result<BoxHeader> {
 }
 
 /// Parse the extra header fields for a full box.
-fn read_fullbox_extra<T: ReadBytesExt>(src: &mut T) -> Result<(u8, u32)> {
+fn read_fullbox_extra<T: ByteSource>(src: &mut T) -> Result<(u8, u32)> {
     let version = src.read_u8()?;
     let flags_a = src.read_u8()?;
     let flags_b = src.read_u8()?;
@@ -742,18 +1600,18 @@ fn read_fullbox_extra<T: ReadBytesExt>(src: &mut T) -> Result<(u8, u32)> {
 }
 
 // Parse the extra fields for a full box whose flag fields must be zero.
-fn read_fullbox_version_no_flags<T: ReadBytesExt>(src: &mut T) -> Result<u8> {
+fn read_fullbox_version_no_flags<T: ByteSource>(src: &mut T) -> Result<u8> {
     let (version, flags) = read_fullbox_extra(src)?;
 
     if flags != 0 {
-        return Err(Error::Unsupported("expected flags to be 0"));
+        return Err(Error::Unsupported(UnsupportedFeature::Other("expected flags to be 0")));
     }
 
     Ok(version)
 }
 
 /// Skip over the entire contents of a box.
-fn skip_box_content<T: Read>(src: &mut BMFFBox<T>) -> Result<()> {
+fn skip_box_content<T: ByteSource>(src: &mut BMFFBox<T>) -> Result<()> {
     // Skip the contents of unknown chunks.
     let to_skip = {
         let header = src.get_header();
@@ -767,8 +1625,16 @@ fn skip_box_content<T: Read>(src: &mut BMFFBox<T>) -> Result<()> {
     skip(src, to_skip)
 }
 
+/// Skip over a box that isn't needed, reporting boxes of unknown types
+fn skip_ignored_box<T: ByteSource>(b: &mut BMFFBox<T>, ctx: &mut ParseContext) -> Result<()> {
+    if matches!(b.head.name, BoxType::UnknownBox(_)) {
+        ctx.warn(b, WarningCode::IgnoredBox, "unknown box has been skipped")?;
+    }
+    skip_box_content(b)
+}
+
 /// Skip over the remain data of a box.
-fn skip_box_remain<T: Read>(src: &mut BMFFBox<T>) -> Result<()> {
+fn skip_box_remain<T: ByteSource>(src: &mut BMFFBox<T>) -> Result<()> {
     let remain = {
         let header = src.get_header();
         let len = src.bytes_left();
@@ -778,12 +1644,256 @@ fn skip_box_remain<T: Read>(src: &mut BMFFBox<T>) -> Result<()> {
     skip(src, remain)
 }
 
+/// Types of nested boxes, from the top level, e.g. `meta/iprp/ipco`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BoxPath(ArrayVec<[u8; 4], 8>);
+
+impl BoxPath {
+    #[must_use]
+    pub fn as_slice(&self) -> &[[u8; 4]] {
+        &self.0
+    }
+
+    /// Boxes nested too deeply are omitted
+    fn with(&self, name: BoxType) -> Self {
+        let mut path = self.clone();
+        let _ = path.0.try_push(FourCC::from(name).value);
+        path
+    }
+}
+
+impl core::fmt::Display for BoxPath {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, name) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("/")?;
+            }
+            FourCC::from(*name).fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Kind of a [`ParseWarning`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WarningCode {
+    /// Box of an unknown type has been skipped
+    IgnoredBox,
+    /// Box has more data than has been parsed
+    UnreadBoxContent,
+    /// Box extends beyond the end of the file
+    TruncatedBox,
+    /// Flags or version of a box don't match the spec
+    UnexpectedFlags,
+    /// Property box couldn't be parsed, and has been ignored
+    InvalidProperty,
+    /// Item has more than one property of the same type
+    DuplicateProperty,
+    /// The primary item is marked as hidden, which is not allowed
+    HiddenPrimaryItem,
+    /// Bytes at the end of a box haven't been parsed, and have been dropped
+    DroppedBoxContent,
+    /// The major brand isn't `avif`, and the file has been accepted because `avif` is a compatible brand
+    CompatibleBrand,
+}
+
+/// Non-fatal issue found in the file, see [`AvifData::warnings`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ParseWarning {
+    pub code: WarningCode,
+    pub message: &'static str,
+    /// The box the issue is in
+    pub box_path: BoxPath,
+    /// Offset of the box from the beginning of the file
+    pub offset: u64,
+}
+
+impl core::fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} in '{}' at {}", self.message, self.box_path, self.offset)
+    }
+}
+
+/// How to handle deviations from the specs, see [`ParseOptions::strictness`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Strictness {
+    /// Reject all detected deviations from the specs, e.g. for conformance checking
+    Strict,
+    /// Accept deviations that are common in files in the wild
+    #[default]
+    Normal,
+    /// Also recover from known encoder bugs: unparseable or unexpected property boxes are ignored,
+    /// truncated boxes at the end of the file are accepted, and duplicate properties keep the first one.
+    Lenient,
+}
+
+/// Limits for parsing untrusted files, see [`read_avif_with_options()`]
+///
+/// All limits are `None` (unlimited) by default. Exceeding a limit is reported as [`Error::LimitExceeded`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ParseOptions {
+    /// Maximum size of the file, checked against the sizes of top-level boxes
+    pub max_file_size: Option<u64>,
+    /// Maximum number of entries in `iloc`, `iinf` and `ipma` boxes
+    pub max_item_count: Option<u32>,
+    /// Maximum number of extents of a single item
+    pub max_extents_per_item: Option<u16>,
+    /// Maximum width and height, checked against `ispe` properties and AV1 sequence headers
+    pub max_dimensions: Option<(u32, u32)>,
+    /// Approximate maximum number of bytes allocated for the file's boxes and items
+    pub max_alloc_size: Option<u64>,
+    /// How to handle files that don't follow the specs
+    pub strictness: Strictness,
+}
+
+/// Options and state threaded through the readers
+#[derive(Default)]
+struct ParseContext {
+    options: ParseOptions,
+    allocated: u64,
+    warnings: TryVec<ParseWarning>,
+    /// Box path and offset of the innermost box of the last error, see [`ErrorContext`]
+    error_location: Option<(BoxPath, u64)>,
+}
+
+impl ParseContext {
+    fn new(options: &ParseOptions) -> Self {
+        Self { options: *options, ..Self::default() }
+    }
+
+    /// Runs `f` with the box, and records it as the location of an error from `f`, unless the error is from a nested box
+    fn in_box<T, R>(&mut self, b: &mut BMFFBox<T>, f: impl FnOnce(&mut BMFFBox<T>, &mut Self) -> Result<R>) -> Result<R> {
+        // Errors from boxes before this one have been recovered from
+        self.error_location = None;
+        let res = f(b, self);
+        match &res {
+            Ok(_) => {
+                self.error_location = None;
+                // Otherwise only logged when the box is dropped
+                if b.content.limit() > 0 {
+                    self.warn(b, WarningCode::DroppedBoxContent, "unparsed bytes at the end of the box have been dropped")?;
+                }
+            },
+            Err(_) if self.error_location.is_none() => self.error_location = Some((b.path.clone(), b.start)),
+            Err(_) => {},
+        }
+        res
+    }
+
+    /// Records the box as the location of the error
+    #[cold]
+    fn error_at<T>(&mut self, b: &BMFFBox<T>, err: Error) -> Error {
+        self.error_location = Some((b.path.clone(), b.start));
+        err
+    }
+
+    /// Adds the recorded location to the error
+    #[cold]
+    fn error_context(&mut self, error: Error) -> ErrorContext {
+        // Errors outside of boxes have an empty path
+        let (box_path, offset) = self.error_location.take().unwrap_or_default();
+        ErrorContext { error, box_path, offset }
+    }
+
+    fn warn<T>(&mut self, b: &BMFFBox<T>, code: WarningCode, message: &'static str) -> Result<()> {
+        let warning = ParseWarning { code, message, box_path: b.path.clone(), offset: b.start };
+        warn!("{warning}");
+        self.warnings.push(warning)?;
+        Ok(())
+    }
+
+    fn is_strict(&self) -> bool {
+        self.options.strictness == Strictness::Strict
+    }
+
+    fn is_lenient(&self) -> bool {
+        self.options.strictness == Strictness::Lenient
+    }
+
+    /// `mdat` cut off by the end of the file is accepted as long as the items are in it, except in strict mode
+    fn check_truncated_mdat(&self, is_truncated: bool) -> Result<()> {
+        if is_truncated && self.is_strict() {
+            return Err(Error::InvalidData("mdat is truncated"));
+        }
+        Ok(())
+    }
+
+    /// Call before allocating `bytes`
+    fn allocate(&mut self, bytes: u64) -> Result<()> {
+        self.allocated = self.allocated.saturating_add(bytes);
+        match self.options.max_alloc_size {
+            Some(max) if self.allocated > max => Err(Error::LimitExceeded("max_alloc_size")),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_file_size(&self, end_offset: u64) -> Result<()> {
+        match self.options.max_file_size {
+            Some(max) if end_offset > max => Err(Error::LimitExceeded("max_file_size")),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_item_count(&self, count: u32) -> Result<()> {
+        match self.options.max_item_count {
+            Some(max) if count > max => Err(Error::LimitExceeded("max_item_count")),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_dimensions(&self, width: u32, height: u32) -> Result<()> {
+        match self.options.max_dimensions {
+            Some((max_width, max_height)) if width > max_width || height > max_height => Err(Error::LimitExceeded("max_dimensions")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks dimensions in the AV1 sequence headers, since they may differ from `ispe`.
+    ///
+    /// Sequence headers in `av1C` are checked before the items are read. This catches the ones that are only in the item data.
+    fn check_av1_dimensions(&self, primary_item: &[u8], alpha_item: Option<&[u8]>) -> Result<()> {
+        if self.options.max_dimensions.is_none() {
+            return Ok(());
+        }
+        for item in [Some(primary_item), alpha_item].into_iter().flatten() {
+            if let Ok(meta) = AV1Metadata::parse_av1_bitstream(item) {
+                self.check_dimensions(meta.max_frame_width.get(), meta.max_frame_height.get())?;
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Read metadata before loading the whole file
 pub struct AvifHeader<R> {
     meta: AvifInternalMeta,
     offset_reader: OffsetReader<R>,
     mdats: TryVec<MediaDataBox>,
+    /// Contents of `mdat` boxes that were seeked over, see `AvifHeader::from_seekable_reader`
+    skipped_mdats: TryVec<Range<u64>>,
     data: AvifData,
+    ctx: ParseContext,
+    selection: ItemSelection,
+}
+
+/// Items loaded by [`AvifHeader::read_avif()`]
+struct ItemSelection {
+    primary: bool,
+    alpha: bool,
+    auxiliary: bool,
+    thumbnails: bool,
+    metadata: bool,
+    item_ids: TryVec<u32>,
+}
+
+impl Default for ItemSelection {
+    fn default() -> Self {
+        Self { primary: true, alpha: true, auxiliary: false, thumbnails: false, metadata: false, item_ids: TryVec::new() }
+    }
 }
 
 /// Read the contents of an AVIF file
@@ -791,55 +1901,139 @@ pub struct AvifHeader<R> {
 /// Metadata is accumulated and returned in [`AvifData`] struct.
 ///
 /// Use [`AvifHeader::from_reader()`] to read metadata without buffering the whole file first.
-pub fn read_avif<T: Read + ?Sized>(f: &mut T) -> Result<AvifData> {
+pub fn read_avif<T: Input + ?Sized>(f: &mut T) -> Result<AvifData> {
     read_avif_header(f)?.read_avif()
 }
 
-fn read_avif_header<R: Read>(reader: R) -> Result<AvifHeader<R>> {
+/// Like [`read_avif()`], but fails with [`Error::LimitExceeded`] if the file exceeds the limits
+///
+/// Errors come with the location of the box they're in.
+pub fn read_avif_with_options<T: Input + ?Sized>(f: &mut T, options: &ParseOptions) -> Result<AvifData, ErrorContext> {
+    Ok(read_avif_header_with(f, None, ParseContext::new(options))?.read_avif()?)
+}
+
+/// Read the contents of an AVIF file, reading only the primary and alpha items
+///
+/// See [`AvifHeader::from_seekable_reader()`]
+#[cfg(feature = "std")]
+pub fn read_avif_seekable<T: Input + Seek + ?Sized>(f: &mut T) -> Result<AvifData> {
+    AvifHeader::from_seekable_reader(f)?.read_avif_seek()
+}
+
+/// Like [`read_avif_seekable()`], but fails with [`Error::LimitExceeded`] if the file exceeds the limits
+///
+/// Errors come with the location of the box they're in.
+#[cfg(feature = "std")]
+pub fn read_avif_seekable_with_options<T: Input + Seek + ?Sized>(f: &mut T, options: &ParseOptions) -> Result<AvifData, ErrorContext> {
+    Ok(AvifHeader::from_seekable_reader_with_options(f, options)?.read_avif_seek()?)
+}
+
+fn read_avif_header<R: Input>(reader: R) -> Result<AvifHeader<R>, ErrorContext> {
+    read_avif_header_with(reader, None, ParseContext::default())
+}
+
+type SeekFn<R> = fn(&mut OffsetReader<R>, u64) -> Result<()>;
+
+fn read_avif_header_with<R: Input>(reader: R, seek_to: Option<SeekFn<R>>, mut ctx: ParseContext) -> Result<AvifHeader<R>, ErrorContext> {
+    read_avif_header_in(reader, seek_to, &mut ctx).map_err(|e| ctx.error_context(e))
+}
+
+/// If `seek_to` is given, `mdat` boxes before `meta` are skipped instead of buffered
+fn read_avif_header_in<R: Input>(reader: R, seek_to: Option<SeekFn<R>>, ctx: &mut ParseContext) -> Result<AvifHeader<R>> {
     let f = OffsetReader::new(reader);
 
-    let mut iter = BoxIter::new(f);
+    let mut iter = BoxIter::new(f, 0);
 
     // 'ftyp' box must occur first; see ISO 14496-12:2015 § 4.3.1
+    let mut file_type = FileType::default();
     if let Some(mut b) = iter.next_box()? {
+        ctx.check_file_size(b.end_offset())?;
         if b.head.name == BoxType::FileTypeBox {
-            let ftyp = read_ftyp(&mut b)?;
-            if ftyp.major_brand != b"avif" {
-                if ftyp.major_brand == b"avis" {
-                    return Err(Error::Unsupported("Animated AVIF is not supported. Please use real AV1 videos instead."));
-                }
-                warn!("major_brand: {}", ftyp.major_brand);
-                return Err(Error::InvalidData("ftyp must be 'avif'"));
-            }
+            file_type = ctx.in_box(&mut b, read_checked_ftyp)?;
         } else {
-            return Err(Error::InvalidData("'ftyp' box must occur first"));
+            return Err(ctx.error_at(&b, Error::InvalidData("'ftyp' box must occur first")));
         }
     }
 
     let mut mdats = TryVec::new();
+    let mut skipped_mdats = TryVec::new();
     loop {
         let mut b = iter.next_box()?.ok_or(Error::InvalidData("No MediaDataBox"))?;
+        ctx.check_file_size(b.end_offset())?;
         match b.head.name {
             BoxType::MetadataBox => {
-                let meta = read_avif_meta(b)?;
-                let data = init_data(&meta);
+                let meta = read_avif_meta(b, ctx)?;
+                let mut data = init_data(&meta);
+                data.file_type = file_type;
                 return Ok(AvifHeader {
-                    meta, data, mdats, offset_reader: iter.src,
+                    meta, data, mdats, skipped_mdats, offset_reader: iter.src, ctx: core::mem::take(ctx), selection: ItemSelection::default(),
                 })
             },
             // annoyingly, mdat is allowed to appear before meta
             BoxType::MediaDataBox => {
                 if b.bytes_left() > 0 {
                     let offset = b.offset();
-                    let data = b.read_into_try_vec()?;
-                    mdats.push(MediaDataBox { offset, data })?;
+                    if let Some(seek_to) = seek_to {
+                        let end = offset.checked_add(b.bytes_left()).ok_or(Error::InvalidData("mdat size overflow"))?;
+                        seek_to(b.content.get_mut(), end)?;
+                        b.content.set_limit(0);
+                        skipped_mdats.push(offset..end)?;
+                    } else {
+                        ctx.allocate(b.bytes_left())?;
+                        let data = b.read_into_try_vec()?;
+                        mdats.push(MediaDataBox { offset, data })?;
+                    }
                 }
             },
-            _ => skip_box_content(&mut b)?,
+            _ => skip_ignored_box(&mut b, ctx)?,
         }
 
-        check_parser_state(&b.content)?;
+        check_truncated_box(&b, ctx).map_err(|e| ctx.error_at(&b, e))?;
+    }
+}
+
+/// Like `check_parser_state` for boxes that have been read until their end,
+/// but in lenient mode accepts boxes cut off by the end of the file
+fn check_truncated_box<T>(b: &BMFFBox<T>, ctx: &mut ParseContext) -> Result<()> {
+    if ctx.is_lenient() && b.content.limit() > 0 {
+        return ctx.warn(b, WarningCode::TruncatedBox, "box is cut off by the end of the file");
+    }
+    check_parser_state(&b.content)
+}
+
+fn check_ftyp(head: BoxHeader, content: &[u8], ctx: &mut ParseContext) -> Result<FileType> {
+    let mut b = slice_box(head, content, 0);
+    if head.name != BoxType::FileTypeBox {
+        return Err(ctx.error_at(&b, Error::InvalidData("'ftyp' box must occur first")));
+    }
+    ctx.in_box(&mut b, read_checked_ftyp)
+}
+
+fn read_checked_ftyp<T: ByteSource>(b: &mut BMFFBox<T>, ctx: &mut ParseContext) -> Result<FileType> {
+    let ftyp = read_ftyp(b)?;
+    check_ftyp_brand(&ftyp, b, ctx)?;
+    Ok(ftyp)
+}
+
+fn check_ftyp_brand<T>(ftyp: &FileType, b: &BMFFBox<T>, ctx: &mut ParseContext) -> Result<()> {
+    match ftyp.avif_brand() {
+        Some(AvifBrand::Major) => {},
+        Some(AvifBrand::Compatible) => {
+            // Animated AVIF lists `avif` too, for its still image fallback
+            if ftyp.has_brand(*b"avis") || ftyp.has_brand(*b"msf1") {
+                return Err(Error::Unsupported(UnsupportedFeature::Animation));
+            }
+            ctx.warn(b, WarningCode::CompatibleBrand, "major brand isn't 'avif', but 'avif' is a compatible brand")?;
+        },
+        None => {
+            if ftyp.has_brand(*b"avis") {
+                return Err(Error::Unsupported(UnsupportedFeature::Animation));
+            }
+            warn!("major_brand: {}", FourCC::from(ftyp.major_brand));
+            return Err(Error::InvalidData("ftyp must have 'avif' brand"));
+        },
     }
+    Ok(())
 }
 
 fn set_alpha_item_id(meta: &mut AvifInternalMeta) {
@@ -867,17 +2061,24 @@ fn set_alpha_item_id(meta: &mut AvifInternalMeta) {
         });
 }
 
-fn init_data(meta: &AvifInternalMeta) -> AvifData {
+fn init_data<Item: Default>(meta: &AvifInternalMeta) -> AvifData<Item> {
     // Extract HDR metadata properties for the primary item
     let mut content_light_level = None;
     let mut mastering_display = None;
+    let mut primary_spatial_extents = None;
+    let mut alpha_spatial_extents = None;
     for prop in meta.properties.iter() {
         if prop.item_id == meta.primary_item_id {
             match &prop.property {
                 ItemProperty::ContentLightLevel(cll) => content_light_level = Some(*cll),
                 ItemProperty::MasteringDisplayColourVolume(mdcv) => mastering_display = Some(*mdcv),
+                ItemProperty::ImageSpatialExtents(ispe) => primary_spatial_extents = Some(*ispe),
                 _ => {},
             }
+        } else if Some(prop.item_id) == meta.alpha_item_id
+            && let ItemProperty::ImageSpatialExtents(ispe) = &prop.property
+        {
+            alpha_spatial_extents = Some(*ispe);
         }
     }
 
@@ -891,96 +2092,561 @@ fn init_data(meta: &AvifInternalMeta) -> AvifData {
         }),
         content_light_level,
         mastering_display,
+        primary_spatial_extents,
+        alpha_spatial_extents,
         ..Default::default()
     }
 }
 
-fn next_data_box<R: Read>(iter: &mut BoxIter<OffsetReader<R>>) -> Result<MediaDataBox> {
+/// Reads the parts of the next `mdat` box that are covered by `sorted_extents`, skipping the rest
+fn next_data_box<R: Input>(iter: &mut BoxIter<OffsetReader<R>>, sorted_extents: &[(usize, ExtentRange)], ctx: &mut ParseContext) -> Result<TryVec<MediaDataBox>> {
     loop {
         let mut b = iter.next_box()?.ok_or(Error::InvalidData("No MediaDataBox"))?;
+        ctx.check_file_size(b.end_offset())?;
         match b.head.name {
             BoxType::MetadataBox => {
                 return Err(Error::InvalidData("There should be zero or one meta boxes per ISO 14496-12:2015 § 8.11.1.1"));
             },
             BoxType::MediaDataBox => {
                 if b.bytes_left() > 0 {
-                    let offset = b.offset();
-                    let data = b.read_into_try_vec()?;
-                    return Ok(MediaDataBox { offset, data });
+                    let chunks = read_data_box_chunks(&mut b, sorted_extents, ctx)?;
+                    b.skip_bytes(b.bytes_left())?;
+                    ctx.check_truncated_mdat(b.bytes_left() > 0)?;
+                    if !chunks.is_empty() {
+                        return Ok(chunks);
+                    }
                 }
             },
-            _ => skip_box_content(&mut b)?,
+            _ => skip_ignored_box(&mut b, ctx)?,
+        }
+        check_parser_state(&b.content)?;
+    }
+}
+
+/// Reads the (merged) ranges of `sorted_extents` that start within the `mdat` box
+fn read_data_box_chunks<R: Input>(b: &mut BMFFBox<&mut OffsetReader<R>>, sorted_extents: &[(usize, ExtentRange)], ctx: &mut ParseContext) -> Result<TryVec<MediaDataBox>> {
+    let start = b.offset();
+    let end = b.end_offset();
+    let mut chunks: TryVec<MediaDataBox> = TryVec::new();
+    let mut pos = start;
+    for (_, range) in sorted_extents {
+        if range.start() < start {
+            continue;
+        }
+        if range.start() >= end {
+            break;
+        }
+        // An extent crossing the end of the box is reported by `extent_data_range` later
+        let range_end = match range {
+            ExtentRange::WithLength(range) => range.end.clamp(range.start, end),
+            ExtentRange::ToEnd(_) => end,
+        };
+        if range_end <= pos {
+            continue;
+        }
+        if range.start() > pos {
+            let gap = range.start() - pos;
+            if b.skip_bytes(gap)? < gap {
+                break;
+            }
+            pos = range.start();
+        }
+        let len = range_end - pos;
+        ctx.allocate(len)?;
+        let data = Take::new(&mut *b, len).read_to_try_vec(len)?;
+        let read_len = data.len().to_u64();
+        match chunks.last_mut() {
+            Some(chunk) if chunk.offset + chunk.data.len().to_u64() == pos => chunk.data.extend_from_slice(&data)?,
+            _ => chunks.push(MediaDataBox { offset: pos, data })?,
+        }
+        pos += read_len;
+        if read_len < len {
+            break;
+        }
+    }
+    Ok(chunks)
+}
+
+/// Extents of the primary (`true`) and alpha (`false`) items, sorted by their position in the file.
+/// Items in `idat` are left out, see `read_idat_primary_and_alpha`.
+fn sorted_item_extents(meta: &AvifInternalMeta) -> Result<TryVec<(bool, ExtentRange)>> {
+    sorted_extents_of(meta, |loc| {
+        if loc.construction_method == ConstructionMethod::Idat {
+            None
+        } else if loc.item_id == meta.primary_item_id {
+            Some(true)
+        } else if Some(loc.item_id) == meta.alpha_item_id {
+            Some(false)
+        } else {
+            None
+        }
+    })
+}
+
+/// Extents of the items for which `select` returns a key, sorted by their position in the file
+fn sorted_extents_of<K: Copy>(meta: &AvifInternalMeta, mut select: impl FnMut(&ItemLocationBoxItem) -> Option<K>) -> Result<TryVec<(K, ExtentRange)>> {
+    let mut sorted_extents = TryVec::new();
+    for loc in meta.iloc_items.iter() {
+        let Some(key) = select(loc) else {
+            continue;
+        };
+
+        if loc.construction_method != ConstructionMethod::File {
+            return Err(Error::Unsupported(UnsupportedFeature::ConstructionMethod(loc.construction_method as u8)));
+        }
+
+        for extent in &loc.extents {
+            sorted_extents.push((key, extent.extent_range.clone()))?;
+        }
+    }
+
+    sorted_extents.sort_unstable_by_key(|(_, e)| e.start());
+    Ok(sorted_extents)
+}
+
+/// Extents of `item_ids` stored in the file, keyed by the index in `item_ids`.
+/// Items in `idat` are left out, since they have been read with the header. See `read_idat_items`.
+fn sorted_file_extents(meta: &AvifInternalMeta, item_ids: &[u32]) -> Result<TryVec<(usize, ExtentRange)>> {
+    sorted_extents_of(meta, |loc| {
+        if loc.construction_method == ConstructionMethod::Idat {
+            return None;
+        }
+        item_ids.iter().position(|&id| id == loc.item_id)
+    })
+}
+
+/// Copies the data of `item_ids` stored in `idat` to `items`, which are in the same order
+fn read_idat_items(meta: &AvifInternalMeta, item_ids: &[u32], items: &mut [TryVec<u8>], ctx: &mut ParseContext) -> Result<()> {
+    for loc in meta.iloc_items.iter().filter(|loc| loc.construction_method == ConstructionMethod::Idat) {
+        let Some(index) = item_ids.iter().position(|&id| id == loc.item_id) else {
+            continue;
+        };
+        // `idat` has been allocated already, so the copy is counted only once it's made
+        let start = items[index].len();
+        read_idat_extents(meta, loc, &mut items[index])?;
+        ctx.allocate((items[index].len() - start).to_u64())?;
+    }
+    Ok(())
+}
+
+/// Data of the primary and alpha items, if they're stored in `idat`
+fn read_idat_primary_and_alpha(meta: &AvifInternalMeta, ctx: &mut ParseContext) -> Result<[TryVec<u8>; 2]> {
+    let item_ids: ArrayVec<u32, 2> = meta.primary_and_alpha().collect();
+    let mut items = [TryVec::new(), TryVec::new()];
+    read_idat_items(meta, &item_ids, &mut items, ctx)?;
+    Ok(items)
+}
+
+/// Copies `idat` item data for `read_avif_slice`, which can't borrow it from the input
+fn idat_to_cow(data: &[u8]) -> Result<Cow<'static, [u8]>> {
+    let mut owned = alloc::vec::Vec::new();
+    owned.try_reserve(data.len())?;
+    owned.extend_from_slice(data);
+    Ok(Cow::Owned(owned))
+}
+
+fn read_idat_extents(meta: &AvifInternalMeta, loc: &ItemLocationBoxItem, item_data: &mut TryVec<u8>) -> Result<()> {
+    let idat = MediaDataBox { offset: 0, data: meta.idat.as_deref().ok_or(Error::InvalidData("idat missing"))? };
+    for extent in &loc.extents {
+        idat.read_extent(&extent.extent_range, item_data)?;
+    }
+    Ok(())
+}
+
+fn read_item_from_ranges(meta: &AvifInternalMeta, mdats: &[MediaDataBox], skipped_mdats: &[Range<u64>], item_id: u32, fetched: &[(u64, &[u8])], item_data: &mut TryVec<u8>, ctx: &mut ParseContext) -> Result<()> {
+    let loc = meta.iloc_items.iter().find(|loc| loc.item_id == item_id)
+        .ok_or(Error::InvalidData("item not present in iloc"))?;
+    match loc.construction_method {
+        ConstructionMethod::File => {},
+        ConstructionMethod::Idat => {
+            let start = item_data.len();
+            read_idat_extents(meta, loc, item_data)?;
+            return ctx.allocate((item_data.len() - start).to_u64());
+        },
+        method => return Err(Error::Unsupported(UnsupportedFeature::ConstructionMethod(method as u8))),
+    }
+    for extent in &loc.extents {
+        let range = &extent.extent_range;
+        if let Some(mdat) = mdats.iter().find(|mdat| mdat.contains_extent(range)) {
+            ctx.allocate(mdat.extent_data_range(range)?.len().to_u64())?;
+            mdat.read_extent(range, item_data)?;
+            continue;
+        }
+        // Same as in `item_byte_ranges`, the end of `mdat` is known only if it has been skipped
+        let end = match range {
+            ExtentRange::WithLength(range) => Some(range.end),
+            ExtentRange::ToEnd(_) => skipped_mdats.iter().find(|mdat| mdat.contains(&range.start())).map(|mdat| mdat.end),
+        };
+        read_fetched(fetched, range.start(), end, item_data, ctx)?;
+    }
+    Ok(())
+}
+
+/// Copies `start..end` of the file from `fetched` ranges, which may be adjacent or overlapping.
+/// Without `end`, copies until the end of the fetched data.
+fn read_fetched(fetched: &[(u64, &[u8])], start: u64, end: Option<u64>, item_data: &mut TryVec<u8>, ctx: &mut ParseContext) -> Result<()> {
+    if end.is_some_and(|end| end < start) {
+        return Err(Error::InvalidData("range start > end"));
+    }
+    let mut pos = start;
+    while end.is_none_or(|end| pos < end) {
+        // The range reaching furthest from `pos`
+        let rest = fetched.iter()
+            .filter_map(|&(offset, data)| data.get(usize::try_from(pos.checked_sub(offset)?).ok()?..))
+            .filter(|rest| !rest.is_empty())
+            .max_by_key(|rest| rest.len());
+        let Some(rest) = rest else {
+            if end.is_none() && pos > start {
+                break;
+            }
+            return Err(Error::UnexpectedEOF);
+        };
+        let len = end.map_or(rest.len(), |end| rest.len().min(usize::try_from(end - pos).unwrap_or(usize::MAX)));
+        ctx.allocate(len.to_u64())?;
+        item_data.extend_from_slice(&rest[..len])?;
+        pos += len.to_u64();
+    }
+    Ok(())
+}
+
+fn read_avif_body<R: Input>(header: AvifHeader<R>) -> Result<AvifData> {
+    let item_ids = header.selected_item_ids()?;
+    let AvifHeader { meta, offset_reader, mdats, skipped_mdats, data: mut context, mut ctx, .. } = header;
+    if !skipped_mdats.is_empty() {
+        return Err(Error::Unsupported(UnsupportedFeature::Other("mdat has been skipped by from_seekable_reader, use read_avif_seek")));
+    }
+
+    meta.check_not_protected(item_ids.iter().copied())?;
+    let items = read_selected_items(&meta, offset_reader, mdats, &item_ids, &mut ctx)?;
+    assign_items(&meta, &mut context, &item_ids, items)?;
+
+    ctx.check_av1_dimensions(&context.primary_item, context.alpha_item.as_deref())?;
+    context.fill_hdr_from_obus();
+    context.warnings = ctx.warnings;
+    Ok(context)
+}
+
+/// Puts the data of `item_ids` into the primary, alpha, or other items of `context`
+fn assign_items(meta: &AvifInternalMeta, context: &mut AvifData, item_ids: &[u32], items: TryVec<TryVec<u8>>) -> Result<()> {
+    for (&item_id, item_data) in item_ids.iter().zip(items) {
+        if item_id == meta.primary_item_id {
+            context.primary_item = item_data;
+        } else if Some(item_id) == meta.alpha_item_id {
+            context.alpha_item = Some(item_data);
+        } else {
+            context.other_items.push((item_id, item_data))?;
         }
-        check_parser_state(&b.content)?;
     }
+    Ok(())
 }
 
-fn read_avif_body<R: Read>(header: AvifHeader<R>) -> Result<AvifData> {
-    let AvifHeader { meta, offset_reader, mdats, data: mut context } = header;
-
-    let mut iter = BoxIter::new(offset_reader);
-    let mut sorted_extents = TryVec::new();
-    for loc in meta.iloc_items.iter() {
-        let is_primary = if loc.item_id == meta.primary_item_id {
-            true
-        } else if Some(loc.item_id) == meta.alpha_item_id {
-            false
-        } else {
-            continue;
-        };
+/// Reads the data of the items selected by `item_ids`, in the same order
+fn read_items_body<R: Input>(header: AvifHeader<R>, item_ids: &[u32]) -> Result<TryVec<TryVec<u8>>> {
+    let AvifHeader { meta, offset_reader, mdats, skipped_mdats, mut ctx, .. } = header;
 
-        if loc.construction_method != ConstructionMethod::File {
-            return Err(Error::Unsupported("unsupported construction_method"));
+    for &item_id in item_ids {
+        if !meta.iloc_items.iter().any(|loc| loc.item_id == item_id) {
+            return Err(Error::InvalidData("item not present in iloc"));
         }
+    }
+    if !skipped_mdats.is_empty() && !sorted_file_extents(&meta, item_ids)?.is_empty() {
+        return Err(Error::Unsupported(UnsupportedFeature::Other("mdat has been skipped by from_seekable_reader, use read_avif_seek")));
+    }
 
-        for extent in &loc.extents {
-            sorted_extents.push((is_primary, extent.extent_range.clone()))?;
+    let mut items = read_selected_items(&meta, offset_reader, mdats, item_ids, &mut ctx)?;
+
+    // The same item may have been requested more than once
+    for (index, &item_id) in item_ids.iter().enumerate() {
+        if let Some(first) = item_ids[..index].iter().position(|&id| id == item_id) {
+            let mut copy = TryVec::new();
+            copy.extend_from_slice(&items[first])?;
+            items[index] = copy;
         }
     }
+    Ok(items)
+}
+
+/// Reads the data of `item_ids` from `idat` and from `mdat` boxes. Extents of other items aren't buffered.
+fn read_selected_items<R: Input>(meta: &AvifInternalMeta, offset_reader: OffsetReader<R>, mdats: TryVec<MediaDataBox>, item_ids: &[u32], ctx: &mut ParseContext) -> Result<TryVec<TryVec<u8>>> {
+    let mut items = TryVec::with_capacity(item_ids.len())?;
+    for _ in item_ids {
+        items.push(TryVec::new())?;
+    }
 
-    sorted_extents.sort_unstable_by_key(|(_, e)| e.start());
+    let sorted_extents = sorted_file_extents(meta, item_ids)?;
+    read_idat_items(meta, item_ids, &mut items, ctx)?;
+
+    if !sorted_extents.is_empty() {
+        let offset = offset_reader.offset;
+        let mut iter = BoxIter::new(offset_reader, offset);
+        read_sorted_extents(&mut iter, mdats, &sorted_extents, &mut items, ctx)?;
+    }
+    Ok(items)
+}
+
+/// Copies extents from `mdat` boxes into `items[key]`, reading boxes from `iter` as needed
+fn read_sorted_extents<R: Input>(iter: &mut BoxIter<OffsetReader<R>>, mdats: TryVec<MediaDataBox>, sorted_extents: &[(usize, ExtentRange)], items: &mut [TryVec<u8>], ctx: &mut ParseContext) -> Result<()> {
     let mut mdats = mdats.into_iter().fuse(); // reading automatically sorted mdats by offset
+    let mut chunks = TryVec::new().into_iter();
     let mut current_mdat: Option<MediaDataBox> = None;
-    for (is_primary, range) in sorted_extents {
+    for (i, (index, range)) in sorted_extents.iter().enumerate() {
         // try to find an overlapping mdat
         let mdat = loop {
             match &mut current_mdat {
-                Some(mdat) if mdat.contains_extent(&range) => break mdat,
+                Some(mdat) if mdat.contains_extent(range) => break mdat,
                 _ => {
-                    current_mdat = mdats.next();
+                    current_mdat = mdats.next().or_else(|| chunks.next());
                     if current_mdat.is_none() {
-                        current_mdat = Some(next_data_box(&mut iter)?);
+                        chunks = next_data_box(iter, &sorted_extents[i..], ctx)?.into_iter();
+                        current_mdat = chunks.next();
                     }
                 },
             }
         };
 
+        let item_data = &mut items[*index];
+        // The data can be moved out unless the next extent needs it too
+        let is_shared = sorted_extents.get(i + 1).is_some_and(|(_, next)| mdat.contains_extent(next));
+        if item_data.is_empty() && !is_shared && mdat.matches_extent(range) {
+            *item_data = core::mem::take(&mut mdat.data);
+        } else if mdat.contains_extent(range) {
+            ctx.allocate(mdat.extent_data_range(range)?.len().to_u64())?;
+            mdat.read_extent(range, item_data)?;
+        } else {
+            return Err(Error::InvalidData("iloc contains an extent that is not in mdat"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+fn read_avif_body_seek<R: Input + Seek>(header: AvifHeader<R>) -> Result<AvifData> {
+    let item_ids = header.selected_item_ids()?;
+    let AvifHeader { meta, mut offset_reader, mut mdats, skipped_mdats: mut mdats_on_disk, data: mut context, mut ctx, .. } = header;
+
+    meta.check_not_protected(item_ids.iter().copied())?;
+    let mut items = TryVec::with_capacity(item_ids.len())?;
+    for _ in item_ids.iter() {
+        items.push(TryVec::new())?;
+    }
+    read_idat_items(&meta, &item_ids, &mut items, &mut ctx)?;
+
+    // Top-level boxes after `meta` are scanned only when an extent is beyond them
+    let mut next_box_offset = offset_reader.offset;
+    for (index, range) in sorted_file_extents(&meta, &item_ids)? {
+        let item_data = &mut items[index];
+
+        if let Some(mdat) = mdats.iter_mut().find(|mdat| mdat.contains_extent(&range)) {
+            if item_data.is_empty() && mdat.matches_extent(&range) {
+                *item_data = core::mem::take(&mut mdat.data);
+            } else {
+                ctx.allocate(mdat.extent_data_range(&range)?.len().to_u64())?;
+                mdat.read_extent(&range, item_data)?;
+            }
+            continue;
+        }
+
+        let mdat = loop {
+            if let Some(mdat) = mdats_on_disk.iter().find(|mdat| mdat.contains(&range.start())) {
+                break mdat.clone();
+            }
+            offset_reader.seek_to(next_box_offset)?;
+            let head = match read_box_header(&mut offset_reader) {
+                Ok(head) => head,
+                Err(Error::UnexpectedEOF) => return Err(Error::InvalidData("iloc contains an extent that is not in mdat")),
+                Err(e) => return Err(e),
+            };
+            match head.name {
+                BoxType::MetadataBox => {
+                    return Err(Error::InvalidData("There should be zero or one meta boxes per ISO 14496-12:2015 § 8.11.1.1"));
+                },
+                BoxType::MediaDataBox if head.size > head.offset => {
+                    mdats_on_disk.push(next_box_offset + head.offset..next_box_offset + head.size)?;
+                },
+                _ => {},
+            }
+            next_box_offset = next_box_offset.checked_add(head.size).ok_or(Error::InvalidData("box size overflow"))?;
+            ctx.check_file_size(next_box_offset)?;
+        };
+
+        let end = match &range {
+            ExtentRange::WithLength(range) => range.end,
+            ExtentRange::ToEnd(_) => mdat.end,
+        };
+        if end > mdat.end || end < range.start() {
+            return Err(Error::InvalidData("extent crosses box boundary"));
+        }
+        ctx.allocate(end - range.start())?;
+        offset_reader.seek_to(range.start())?;
+        let start = item_data.len();
+        item_data.resize_with(start + usize::try_from(end - range.start())?, || 0)?;
+        offset_reader.read_exact(&mut item_data[start..])?;
+    }
+    if ctx.is_strict() {
+        for mdat in &mdats_on_disk {
+            ctx.check_truncated_mdat(offset_reader.is_truncated_at(mdat.end)?)?;
+        }
+    }
+    assign_items(&meta, &mut context, &item_ids, items)?;
+
+    ctx.check_av1_dimensions(&context.primary_item, context.alpha_item.as_deref())?;
+    context.fill_hdr_from_obus();
+    context.warnings = ctx.warnings;
+    Ok(context)
+}
+
+/// Read the contents of an AVIF file that is already in memory
+///
+/// Items stored in a single extent are borrowed from `data` without copying.
+/// Only items split into multiple extents or stored in `idat` are copied.
+///
+/// Only the primary and alpha items are read. Use [`AvifHeader::from_reader()`] with `&data[..]` to select other items.
+pub fn read_avif_slice(data: &[u8]) -> Result<AvifDataRef<'_>> {
+    read_avif_slice_with(data, &mut ParseContext::default())
+}
+
+/// Like [`read_avif_slice()`], but fails with [`Error::LimitExceeded`] if the file exceeds the limits
+///
+/// Errors come with the location of the box they're in.
+pub fn read_avif_slice_with_options<'a>(data: &'a [u8], options: &ParseOptions) -> Result<AvifDataRef<'a>, ErrorContext> {
+    let mut ctx = ParseContext::new(options);
+    read_avif_slice_with(data, &mut ctx).map_err(|e| ctx.error_context(e))
+}
+
+fn read_avif_slice_with<'a>(data: &'a [u8], ctx: &mut ParseContext) -> Result<AvifDataRef<'a>> {
+    ctx.check_file_size(data.len().to_u64())?;
+    let mut boxes = SliceBoxIter { data, offset: 0 };
+
+    // 'ftyp' box must occur first; see ISO 14496-12:2015 § 4.3.1
+    let mut file_type = FileType::default();
+    if let Some((head, content)) = boxes.next_box()? {
+        file_type = check_ftyp(head, content, ctx)?;
+    }
+
+    let mut mdats = TryVec::new();
+    let meta = loop {
+        let (head, content) = boxes.next_box()?.ok_or(Error::InvalidData("No MediaDataBox"))?;
+        match head.name {
+            BoxType::MetadataBox => {
+                let start = boxes.offset - content.len().to_u64() - head.offset;
+                break read_avif_meta(slice_box(head, content, start), ctx)?;
+            },
+            // annoyingly, mdat is allowed to appear before meta
+            BoxType::MediaDataBox if !content.is_empty() => {
+                mdats.push(MediaDataBox { offset: boxes.offset - content.len().to_u64(), data: content })?;
+            },
+            _ => {},
+        }
+    };
+
+    meta.check_not_protected(meta.primary_and_alpha())?;
+    let mut context: AvifDataRef<'_> = init_data(&meta);
+    context.file_type = file_type;
+    context.primary_item = Cow::Borrowed(&[]);
+    context.warnings = core::mem::take(&mut ctx.warnings);
+    let [primary_idat, alpha_idat] = read_idat_primary_and_alpha(&meta, ctx)?;
+    if !primary_idat.is_empty() {
+        context.primary_item = idat_to_cow(&primary_idat)?;
+    }
+    if !alpha_idat.is_empty() {
+        context.alpha_item = Some(idat_to_cow(&alpha_idat)?);
+    }
+    for (is_primary, range) in sorted_item_extents(&meta)? {
+        let extent = loop {
+            if let Some(mdat) = mdats.iter().find(|mdat| mdat.contains_extent(&range)) {
+                let data: &[u8] = mdat.data;
+                break &data[mdat.extent_data_range(&range)?];
+            }
+            let (head, content) = boxes.next_box()?.ok_or(Error::InvalidData("No MediaDataBox"))?;
+            match head.name {
+                BoxType::MetadataBox => {
+                    return Err(Error::InvalidData("There should be zero or one meta boxes per ISO 14496-12:2015 § 8.11.1.1"));
+                },
+                BoxType::MediaDataBox if !content.is_empty() => {
+                    ctx.check_truncated_mdat(content.len().to_u64() < head.size - head.offset)?;
+                    mdats.push(MediaDataBox { offset: boxes.offset - content.len().to_u64(), data: content })?;
+                },
+                _ => {},
+            }
+        };
+
         let item_data = if is_primary {
             &mut context.primary_item
         } else {
-            context.alpha_item.get_or_insert_with(TryVec::new)
+            context.alpha_item.get_or_insert(Cow::Borrowed(&[]))
         };
-        if item_data.is_empty() && mdat.matches_extent(&range) {
-            *item_data = std::mem::take(&mut mdat.data);
-        } else if mdat.contains_extent(&range) {
-            mdat.read_extent(&range, item_data)?;
+        if item_data.is_empty() {
+            *item_data = Cow::Borrowed(extent);
         } else {
-            return Err(Error::InvalidData("iloc contains an extent that is not in mdat"));
+            if let Cow::Borrowed(first) = *item_data {
+                // Like `to_mut()`, but doesn't abort if the allocation fails
+                let mut owned = alloc::vec::Vec::new();
+                ctx.allocate(first.len().to_u64())?;
+                owned.try_reserve(first.len() + extent.len())?;
+                owned.extend_from_slice(first);
+                *item_data = Cow::Owned(owned);
+            }
+            let joined = item_data.to_mut();
+            ctx.allocate(extent.len().to_u64())?;
+            joined.try_reserve(extent.len())?;
+            joined.extend_from_slice(extent);
         }
     }
 
+    ctx.check_av1_dimensions(&context.primary_item, context.alpha_item.as_deref())?;
+    context.fill_hdr_from_obus();
     Ok(context)
 }
 
+/// Top-level boxes of an in-memory file, without copying their content
+struct SliceBoxIter<'a> {
+    data: &'a [u8],
+    /// Offset of `data` from the beginning of the file
+    offset: u64,
+}
+
+impl<'a> SliceBoxIter<'a> {
+    fn next_box(&mut self) -> Result<Option<(BoxHeader, &'a [u8])>> {
+        let mut header_data = self.data;
+        let head = match read_box_header(&mut header_data) {
+            Ok(h) => h,
+            Err(Error::UnexpectedEOF) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        // Like `Take`, a truncated box ends at the end of the data
+        let end = self.data.len().min(head.size.try_into().unwrap_or(usize::MAX));
+        let content = self.data.get(head.offset.try_into()?..end).ok_or(Error::UnexpectedEOF)?;
+        self.data = &self.data[end..];
+        self.offset += end.to_u64();
+        Ok(Some((head, content)))
+    }
+}
+
+/// `start` is the offset of the box header from the beginning of the file
+fn slice_box(head: BoxHeader, content: &[u8], start: u64) -> BMFFBox<&[u8]> {
+    BMFFBox { head, content: Take::new(content, content.len().to_u64()), start, path: BoxPath::default().with(head.name) }
+}
+
 /// Parse a metadata box in the context of an AVIF
 /// Currently requires the primary item to be an av01 item type and generates
 /// an error otherwise.
 /// See ISO 14496-12:2015 § 8.11.1
-fn read_avif_meta<T: Read>(mut src: BMFFBox<T>) -> Result<AvifInternalMeta> {
-    let version = read_fullbox_version_no_flags(&mut src)?;
+fn read_avif_meta<T: ByteSource>(mut src: BMFFBox<T>, ctx: &mut ParseContext) -> Result<AvifInternalMeta> {
+    ctx.in_box(&mut src, read_meta_content)
+}
+
+fn read_meta_content<T: ByteSource>(src: &mut BMFFBox<T>, ctx: &mut ParseContext) -> Result<AvifInternalMeta> {
+    let meta = read_meta_boxes(src, ctx)?;
+    check_primary_item(&meta, src, ctx)?;
+    Ok(meta)
+}
+
+/// Like `read_meta_content`, but the primary item may be of any type
+fn read_meta_boxes<T: ByteSource>(src: &mut BMFFBox<T>, ctx: &mut ParseContext) -> Result<AvifInternalMeta> {
+    let version = read_fullbox_version_no_flags(src)?;
 
     if version != 0 {
-        return Err(Error::Unsupported("unsupported meta version"));
+        return Err(Error::Unsupported(UnsupportedFeature::BoxVersion { box_type: *b"meta", version }));
     }
 
     let mut primary_item_id = None;
@@ -988,77 +2654,122 @@ fn read_avif_meta<T: Read>(mut src: BMFFBox<T>) -> Result<AvifInternalMeta> {
     let mut iloc_items = None;
     let mut item_references = TryVec::new();
     let mut properties = TryVec::new();
+    let mut idat = None;
+    let mut protection_schemes = None;
 
     let mut iter = src.box_iter();
     while let Some(mut b) = iter.next_box()? {
-        match b.head.name {
-            BoxType::ItemInfoBox => {
-                if item_infos.is_some() {
-                    return Err(Error::InvalidData("There should be zero or one iinf boxes per ISO 14496-12:2015 § 8.11.6.1"));
-                }
-                item_infos = Some(read_iinf(&mut b)?);
-            },
-            BoxType::ItemLocationBox => {
-                if iloc_items.is_some() {
-                    return Err(Error::InvalidData("There should be zero or one iloc boxes per ISO 14496-12:2015 § 8.11.3.1"));
-                }
-                iloc_items = Some(read_iloc(&mut b)?);
-            },
-            BoxType::PrimaryItemBox => {
-                if primary_item_id.is_some() {
-                    return Err(Error::InvalidData("There should be zero or one iloc boxes per ISO 14496-12:2015 § 8.11.4.1"));
-                }
-                primary_item_id = Some(read_pitm(&mut b)?);
-            },
-            BoxType::ImageReferenceBox => {
-                read_iref(&mut b, &mut item_references)?;
-            },
-            BoxType::ImagePropertiesBox => {
-                read_iprp(&mut b, &mut properties)?;
-            },
-            _ => skip_box_content(&mut b)?,
-        }
+        ctx.in_box(&mut b, |b, ctx| {
+            match b.head.name {
+                BoxType::ItemInfoBox => {
+                    if item_infos.is_some() {
+                        return Err(Error::InvalidData("There should be zero or one iinf boxes per ISO 14496-12:2015 § 8.11.6.1"));
+                    }
+                    item_infos = Some(read_iinf(b, ctx)?);
+                },
+                BoxType::ItemLocationBox => {
+                    if iloc_items.is_some() {
+                        return Err(Error::InvalidData("There should be zero or one iloc boxes per ISO 14496-12:2015 § 8.11.3.1"));
+                    }
+                    iloc_items = Some(read_iloc(b, ctx)?);
+                },
+                BoxType::PrimaryItemBox => {
+                    if primary_item_id.is_some() {
+                        return Err(Error::InvalidData("There should be zero or one iloc boxes per ISO 14496-12:2015 § 8.11.4.1"));
+                    }
+                    primary_item_id = Some(read_pitm(b)?);
+                },
+                BoxType::ImageReferenceBox => {
+                    read_iref(b, &mut item_references, ctx)?;
+                },
+                BoxType::ImagePropertiesBox => {
+                    read_iprp(b, &mut properties, ctx)?;
+                },
+                BoxType::ItemProtectionBox => {
+                    if protection_schemes.is_some() {
+                        return Err(Error::InvalidData("There should be zero or one ipro boxes per ISO 14496-12:2015 § 8.11.5.1"));
+                    }
+                    protection_schemes = Some(read_ipro(b, ctx)?);
+                },
+                BoxType::ItemDataBox => {
+                    if idat.is_some() {
+                        return Err(Error::InvalidData("There should be zero or one idat boxes per ISO 14496-12:2015 § 8.11.11.1"));
+                    }
+                    ctx.allocate(b.bytes_left())?;
+                    idat = Some(b.read_into_try_vec()?);
+                },
+                _ => {
+                    skip_ignored_box(b, ctx)?;
+                    return check_truncated_box(b, ctx);
+                },
+            }
 
-        check_parser_state(&b.content)?;
+            check_parser_state(&b.content)
+        })?;
     }
     check_parser_state(&src.content)?;
 
+    for prop in properties.iter() {
+        if let ItemProperty::ImageSpatialExtents(ispe) = &prop.property {
+            ctx.check_dimensions(ispe.width, ispe.height)?;
+        }
+    }
+
     let primary_item_id = primary_item_id.ok_or(Error::InvalidData("Required pitm box not present in meta box"))?;
 
     let item_infos = item_infos.ok_or(Error::InvalidData("iinf missing"))?;
 
-    if let Some(item_info) = item_infos.iter().find(|x| x.item_id == primary_item_id) {
-        if item_info.item_type != b"av01" {
-            if item_info.item_type == b"grid" {
-                return Err(Error::Unsupported("Grid-based AVIF collage is not supported"));
-            }
-            warn!("primary_item_id type: {}", item_info.item_type);
-            return Err(Error::InvalidData("primary_item_id type is not av01"));
-        }
-    } else {
-        return Err(Error::InvalidData("primary_item_id not present in iinf box"));
-    }
-
     let mut meta = AvifInternalMeta {
+        item_infos,
         properties,
         item_references,
         primary_item_id,
         alpha_item_id: None,
         iloc_items: iloc_items.ok_or(Error::InvalidData("iloc missing"))?,
+        idat,
+        protection_schemes: protection_schemes.unwrap_or_default(),
     };
     set_alpha_item_id(&mut meta);
     Ok(meta)
 }
 
+/// Requires the primary item to be a visible `av01` item
+fn check_primary_item<T>(meta: &AvifInternalMeta, src: &BMFFBox<T>, ctx: &mut ParseContext) -> Result<()> {
+    if let Some(item_info) = meta.item_infos.iter().find(|x| x.item_id == meta.primary_item_id) {
+        // See ISO 23008-12:2017 § 6.4.2
+        if item_info.hidden {
+            if !ctx.is_lenient() {
+                return Err(Error::InvalidData("primary item must not be hidden"));
+            }
+            ctx.warn(src, WarningCode::HiddenPrimaryItem, "primary item is hidden")?;
+        }
+        // Types of protected items may be replaced, with the original type in `frma`
+        let original_format = usize::from(item_info.protection_index).checked_sub(1)
+            .and_then(|index| meta.protection_schemes.get(index)?.original_format);
+        let item_type = original_format.unwrap_or(item_info.item_type.value);
+        if item_type != *b"av01" {
+            // Derived image item types, see ISO 23008-12:2017 § 6.6.2
+            if matches!(&item_type, b"grid" | b"iovl" | b"iden") {
+                return Err(Error::Unsupported(UnsupportedFeature::DerivedImage(item_type)));
+            }
+            warn!("primary_item_id type: {}", item_info.item_type);
+            return Err(Error::InvalidData("primary_item_id type is not av01"));
+        }
+    } else {
+        return Err(Error::InvalidData("primary_item_id not present in iinf box"));
+    }
+    Ok(())
+}
+
 /// Parse a Primary Item Box
 /// See ISO 14496-12:2015 § 8.11.4
-fn read_pitm<T: Read>(src: &mut BMFFBox<T>) -> Result<u32> {
+fn read_pitm<T: ByteSource>(src: &mut BMFFBox<T>) -> Result<u32> {
     let version = read_fullbox_version_no_flags(src)?;
 
     let item_id = match version {
         0 => be_u16(src)?.into(),
         1 => be_u32(src)?,
-        _ => return Err(Error::Unsupported("unsupported pitm version")),
+        _ => return Err(Error::Unsupported(UnsupportedFeature::BoxVersion { box_type: *b"pitm", version })),
     };
 
     Ok(item_id)
@@ -1066,30 +2777,34 @@ fn read_pitm<T: Read>(src: &mut BMFFBox<T>) -> Result<u32> {
 
 /// Parse an Item Information Box
 /// See ISO 14496-12:2015 § 8.11.6
-fn read_iinf<T: Read>(src: &mut BMFFBox<T>) -> Result<TryVec<ItemInfoEntry>> {
+fn read_iinf<T: ByteSource>(src: &mut BMFFBox<T>, ctx: &mut ParseContext) -> Result<TryVec<ItemInfoEntry>> {
     let version = read_fullbox_version_no_flags(src)?;
 
     match version {
         0 | 1 => (),
-        _ => return Err(Error::Unsupported("unsupported iinf version")),
+        _ => return Err(Error::Unsupported(UnsupportedFeature::BoxVersion { box_type: *b"iinf", version })),
     }
 
     let entry_count = if version == 0 {
-        be_u16(src)?.to_usize()
+        be_u16(src)?.into()
     } else {
-        be_u32(src)?.to_usize()
+        be_u32(src)?
     };
-    let mut item_infos = TryVec::with_capacity(entry_count)?;
+    ctx.check_item_count(entry_count)?;
+    ctx.allocate(u64::from(entry_count) * core::mem::size_of::<ItemInfoEntry>().to_u64())?;
+    let mut item_infos = TryVec::with_capacity(entry_count.to_usize())?;
 
     let mut iter = src.box_iter();
     while let Some(mut b) = iter.next_box()? {
-        if b.head.name != BoxType::ItemInfoEntry {
-            return Err(Error::InvalidData("iinf box should contain only infe boxes"));
-        }
+        ctx.in_box(&mut b, |b, ctx| {
+            if b.head.name != BoxType::ItemInfoEntry {
+                return Err(Error::InvalidData("iinf box should contain only infe boxes"));
+            }
 
-        item_infos.push(read_infe(&mut b)?)?;
+            item_infos.push(read_infe(b, ctx)?)?;
 
-        check_parser_state(&b.content)?;
+            check_parser_state(&b.content)
+        })?;
     }
 
     Ok(item_infos)
@@ -1097,82 +2812,249 @@ fn read_iinf<T: Read>(src: &mut BMFFBox<T>) -> Result<TryVec<ItemInfoEntry>> {
 
 /// Parse an Item Info Entry
 /// See ISO 14496-12:2015 § 8.11.6.2
-fn read_infe<T: Read>(src: &mut BMFFBox<T>) -> Result<ItemInfoEntry> {
-    // According to the standard, it seems the flags field should be 0, but
-    // at least one sample AVIF image has a nonzero value.
-    let (version, _) = read_fullbox_extra(src)?;
+fn read_infe<T: ByteSource>(src: &mut BMFFBox<T>, ctx: &mut ParseContext) -> Result<ItemInfoEntry> {
+    // Flag bit 0 marks hidden items, see ISO 23008-12:2017 § 9.2
+    let (version, flags) = read_fullbox_extra(src)?;
+    if flags & !1 != 0 {
+        if ctx.is_strict() {
+            return Err(Error::InvalidData("infe flags other than hidden must be 0"));
+        }
+        ctx.warn(src, WarningCode::UnexpectedFlags, "infe flags other than hidden should be 0")?;
+    }
 
     // mif1 brand (see ISO 23008-12:2017 § 10.2.1) only requires v2 and 3
     let item_id = match version {
-        2 => be_u16(src)?.into(),
+        0..=2 => be_u16(src)?.into(),
         3 => be_u32(src)?,
-        _ => return Err(Error::Unsupported("unsupported version in 'infe' box")),
+        _ => return Err(Error::Unsupported(UnsupportedFeature::BoxVersion { box_type: *b"infe", version })),
+    };
+
+    let protection_index = be_u16(src)?;
+
+    // Versions 0 and 1 describe items only by their content type
+    let item_type = if version >= 2 { FourCC::from(be_u32(src)?) } else { FourCC::from(*b"mime") };
+    debug!("infe item_id {item_id} item_type: {item_type}");
+
+    // Null-terminated strings, but the last terminator is sometimes missing.
+    // Version 1 may have an extension after them, which is ignored.
+    ctx.allocate(src.bytes_left())?;
+    let strings = src.read_into_try_vec()?;
+    let mut strings = strings.split(|&b| b == 0);
+    let mut name = TryString::new();
+    name.extend_from_slice(strings.next().unwrap_or_default())?;
+    let content_type = if item_type == b"mime" {
+        let mut content_type = TryString::new();
+        content_type.extend_from_slice(strings.next().unwrap_or_default())?;
+        Some(content_type)
+    } else {
+        None
     };
 
-    let item_protection_index = be_u16(src)?;
+    Ok(ItemInfoEntry { item_id, item_type, hidden: flags & 1 != 0, name, content_type, protection_index })
+}
 
-    if item_protection_index != 0 {
-        return Err(Error::Unsupported("protected items (infe.item_protection_index != 0) are not supported"));
+/// Parse an Item Protection Box
+/// See ISO 14496-12:2015 § 8.11.5
+fn read_ipro<T: ByteSource>(src: &mut BMFFBox<T>, ctx: &mut ParseContext) -> Result<TryVec<ProtectionScheme>> {
+    let version = read_fullbox_version_no_flags(src)?;
+    if version != 0 {
+        return Err(Error::Unsupported(UnsupportedFeature::BoxVersion { box_type: *b"ipro", version }));
     }
 
-    let item_type = FourCC::from(be_u32(src)?);
-    debug!("infe item_id {item_id} item_type: {item_type}");
+    let protection_count = be_u16(src)?;
+    ctx.check_item_count(protection_count.into())?;
+    let mut schemes = TryVec::with_capacity(protection_count.into())?;
 
-    // There are some additional fields here, but they're not of interest to us
-    skip_box_remain(src)?;
+    let mut iter = src.box_iter();
+    while let Some(mut b) = iter.next_box()? {
+        ctx.in_box(&mut b, |b, ctx| {
+            if b.head.name != BoxType::ProtectionSchemeInfoBox {
+                return Err(Error::InvalidData("ipro box should contain only sinf boxes"));
+            }
+
+            schemes.push(read_sinf(b, ctx)?)?;
+
+            check_parser_state(&b.content)
+        })?;
+    }
+
+    if schemes.len() != usize::from(protection_count) {
+        return Err(Error::InvalidData("ipro protection_count doesn't match the number of sinf boxes"));
+    }
+    Ok(schemes)
+}
+
+/// Parse a Protection Scheme Information Box
+/// See ISO 14496-12:2015 § 8.12.1
+fn read_sinf<T: ByteSource>(src: &mut BMFFBox<T>, ctx: &mut ParseContext) -> Result<ProtectionScheme> {
+    let mut original_format = None;
+    let mut scheme = None;
+    let mut tenc = None;
+
+    let mut iter = src.box_iter();
+    while let Some(mut b) = iter.next_box()? {
+        ctx.in_box(&mut b, |b, ctx| {
+            match b.head.name {
+                BoxType::OriginalFormatBox => {
+                    original_format = Some(be_u32(b)?.to_be_bytes());
+                },
+                BoxType::SchemeTypeBox => {
+                    // The optional scheme_uri isn't needed
+                    let (version, _) = read_fullbox_extra(b)?;
+                    if version != 0 {
+                        return Err(Error::Unsupported(UnsupportedFeature::BoxVersion { box_type: *b"schm", version }));
+                    }
+                    scheme = Some((be_u32(b)?.to_be_bytes(), be_u32(b)?));
+                    skip_box_remain(b)?;
+                },
+                BoxType::SchemeInformationBox => {
+                    let mut iter = b.box_iter();
+                    while let Some(mut b) = iter.next_box()? {
+                        ctx.in_box(&mut b, |b, ctx| {
+                            if b.head.name == BoxType::TrackEncryptionBox {
+                                tenc = Some(read_tenc(b, ctx)?);
+                            } else {
+                                skip_ignored_box(b, ctx)?;
+                            }
+                            check_parser_state(&b.content)
+                        })?;
+                    }
+                },
+                _ => skip_ignored_box(b, ctx)?,
+            }
+            check_parser_state(&b.content)
+        })?;
+    }
+
+    let (scheme_type, scheme_version) = scheme.ok_or(Error::InvalidData("sinf must contain a schm box"))?;
+    let mut scheme = ProtectionScheme {
+        original_format,
+        scheme_type,
+        scheme_version,
+        key_id: None,
+        per_sample_iv_size: 0,
+        constant_iv: TryVec::new(),
+        crypt_byte_block: 0,
+        skip_byte_block: 0,
+    };
+    if let Some(tenc) = tenc {
+        scheme.key_id = Some(tenc.key_id);
+        scheme.per_sample_iv_size = tenc.per_sample_iv_size;
+        scheme.constant_iv = tenc.constant_iv;
+        scheme.crypt_byte_block = tenc.crypt_byte_block;
+        scheme.skip_byte_block = tenc.skip_byte_block;
+    }
+    Ok(scheme)
+}
+
+/// Default encryption parameters from a `tenc` box
+struct TrackEncryption {
+    key_id: [u8; 16],
+    per_sample_iv_size: u8,
+    constant_iv: TryVec<u8>,
+    crypt_byte_block: u8,
+    skip_byte_block: u8,
+}
+
+/// Parse a Track Encryption Box
+/// See ISO 23001-7:2016 § 8.2
+fn read_tenc<T: ByteSource>(src: &mut BMFFBox<T>, ctx: &mut ParseContext) -> Result<TrackEncryption> {
+    let (version, _) = read_fullbox_extra(src)?;
+    if version > 1 {
+        return Err(Error::Unsupported(UnsupportedFeature::BoxVersion { box_type: *b"tenc", version }));
+    }
+    let _reserved = src.read_u8()?;
+    let pattern = src.read_u8()?;
+    let (crypt_byte_block, skip_byte_block) = if version > 0 { (pattern >> 4, pattern & 0xF) } else { (0, 0) };
+    let is_protected = src.read_u8()?;
+    let per_sample_iv_size = src.read_u8()?;
+    let mut key_id = [0; 16];
+    src.read_exact(&mut key_id)?;
+
+    let mut constant_iv = TryVec::new();
+    if is_protected == 1 && per_sample_iv_size == 0 {
+        let constant_iv_size = src.read_u8()?;
+        ctx.allocate(constant_iv_size.into())?;
+        constant_iv.resize_with(constant_iv_size.into(), || 0)?;
+        src.read_exact(&mut constant_iv)?;
+    }
 
-    Ok(ItemInfoEntry { item_id, item_type })
+    Ok(TrackEncryption { key_id, per_sample_iv_size, constant_iv, crypt_byte_block, skip_byte_block })
 }
 
-fn read_iref<T: Read>(src: &mut BMFFBox<T>, item_references: &mut TryVec<SingleItemTypeReferenceBox>) -> Result<()> {
+fn read_iref<T: ByteSource>(src: &mut BMFFBox<T>, item_references: &mut TryVec<SingleItemTypeReferenceBox>, ctx: &mut ParseContext) -> Result<()> {
     let version = read_fullbox_version_no_flags(src)?;
     if version > 1 {
-        return Err(Error::Unsupported("iref version"));
+        if ctx.is_lenient() {
+            ctx.warn(src, WarningCode::UnexpectedFlags, "iref with unsupported version has been ignored")?;
+            return skip_box_remain(src);
+        }
+        return Err(Error::Unsupported(UnsupportedFeature::BoxVersion { box_type: *b"iref", version }));
     }
 
     let mut iter = src.box_iter();
     while let Some(mut b) = iter.next_box()? {
-        let from_item_id = if version == 0 {
-            be_u16(&mut b)?.into()
+        ctx.in_box(&mut b, |b, _| {
+            let (from_item_id, to_item_ids) = read_item_reference(b, version)?;
+            for to_item_id in to_item_ids {
+                item_references.push(SingleItemTypeReferenceBox {
+                    item_type: b.head.name.into(),
+                    from_item_id,
+                    to_item_id,
+                })?;
+            }
+            check_parser_state(&b.content)
+        })?;
+    }
+    Ok(())
+}
+
+/// Parse a `SingleItemTypeReferenceBox` of an `iref` box with the given version.
+/// Returns `from_item_id` and the `to_item_id`s.
+fn read_item_reference<T: ByteSource>(b: &mut BMFFBox<T>, version: u8) -> Result<(u32, TryVec<u32>)> {
+    let from_item_id = if version == 0 {
+        be_u16(b)?.into()
+    } else {
+        be_u32(b)?
+    };
+    let reference_count = be_u16(b)?;
+    let mut to_item_ids = TryVec::new();
+    for _ in 0..reference_count {
+        let to_item_id = if version == 0 {
+            be_u16(b)?.into()
         } else {
-            be_u32(&mut b)?
+            be_u32(b)?
         };
-        let reference_count = be_u16(&mut b)?;
-        for _ in 0..reference_count {
-            let to_item_id = if version == 0 {
-                be_u16(&mut b)?.into()
-            } else {
-                be_u32(&mut b)?
-            };
-            if from_item_id == to_item_id {
-                return Err(Error::InvalidData("from_item_id and to_item_id must be different"));
-            }
-            item_references.push(SingleItemTypeReferenceBox {
-                item_type: b.head.name.into(),
-                from_item_id,
-                to_item_id,
-            })?;
+        if from_item_id == to_item_id {
+            return Err(Error::InvalidData("from_item_id and to_item_id must be different"));
         }
-        check_parser_state(&b.content)?;
+        to_item_ids.push(to_item_id)?;
     }
-    Ok(())
+    Ok((from_item_id, to_item_ids))
 }
 
-fn read_iprp<T: Read>(src: &mut BMFFBox<T>, associated: &mut TryVec<AssociatedProperty>) -> Result<()> {
+fn read_iprp<T: ByteSource>(src: &mut BMFFBox<T>, associated: &mut TryVec<AssociatedProperty>, ctx: &mut ParseContext) -> Result<()> {
     let mut iter = src.box_iter();
     let mut properties = TryVec::new();
     let mut associations = TryVec::new();
 
     while let Some(mut b) = iter.next_box()? {
-        match b.head.name {
-            BoxType::ItemPropertyContainerBox => {
-                properties = read_ipco(&mut b)?;
-            },
-            BoxType::ItemPropertyAssociationBox => {
-                associations = read_ipma(&mut b)?;
-            },
-            _ => return Err(Error::InvalidData("unexpected ipco child")),
-        }
+        ctx.in_box(&mut b, |b, ctx| {
+            match b.head.name {
+                BoxType::ItemPropertyContainerBox => {
+                    properties = read_ipco(b, ctx)?;
+                },
+                BoxType::ItemPropertyAssociationBox => {
+                    associations = read_ipma(b, ctx)?;
+                },
+                _ if ctx.is_lenient() => {
+                    ctx.warn(b, WarningCode::IgnoredBox, "unexpected iprp child has been skipped")?;
+                    skip_box_remain(b)?;
+                },
+                _ => return Err(Error::InvalidData("unexpected ipco child")),
+            }
+            Ok(())
+        })?;
     }
 
     for a in associations {
@@ -1183,6 +3065,18 @@ fn read_iprp<T: Read>(src: &mut BMFFBox<T>, associated: &mut TryVec<AssociatedPr
         if let Some(prop) = properties.get(index)
             && *prop != ItemProperty::Unsupported
         {
+            let is_duplicate = associated.iter().any(|other| {
+                other.item_id == a.item_id && core::mem::discriminant(&other.property) == core::mem::discriminant(prop)
+            });
+            if is_duplicate {
+                if ctx.is_strict() {
+                    return Err(Error::InvalidData("item has duplicate properties of the same type"));
+                }
+                ctx.warn(src, WarningCode::DuplicateProperty, "item has duplicate properties of the same type")?;
+                if ctx.is_lenient() {
+                    continue;
+                }
+            }
             associated.push(AssociatedProperty {
                 item_id: a.item_id,
                 property: prop.try_clone()?,
@@ -1198,6 +3092,7 @@ pub(crate) enum ItemProperty {
     AuxiliaryType(AuxiliaryTypeProperty),
     ContentLightLevel(ContentLightLevel),
     MasteringDisplayColourVolume(MasteringDisplayColourVolume),
+    ImageSpatialExtents(ImageSpatialExtents),
     Unsupported,
 }
 
@@ -1208,6 +3103,7 @@ impl TryClone for ItemProperty {
             Self::AuxiliaryType(val) => Self::AuxiliaryType(val.try_clone()?),
             Self::ContentLightLevel(val) => Self::ContentLightLevel(*val),
             Self::MasteringDisplayColourVolume(val) => Self::MasteringDisplayColourVolume(*val),
+            Self::ImageSpatialExtents(val) => Self::ImageSpatialExtents(*val),
             Self::Unsupported => Self::Unsupported,
         })
     }
@@ -1215,7 +3111,6 @@ impl TryClone for ItemProperty {
 
 struct Association {
     item_id: u32,
-    #[allow(unused)]
     essential: bool,
     property_index: u16,
 }
@@ -1225,12 +3120,13 @@ pub(crate) struct AssociatedProperty {
     pub property: ItemProperty,
 }
 
-fn read_ipma<T: Read>(src: &mut BMFFBox<T>) -> Result<TryVec<Association>> {
+fn read_ipma<T: ByteSource>(src: &mut BMFFBox<T>, ctx: &mut ParseContext) -> Result<TryVec<Association>> {
     let (version, flags) = read_fullbox_extra(src)?;
 
     let mut associations = TryVec::new();
 
     let entry_count = be_u32(src)?;
+    ctx.check_item_count(entry_count)?;
     for _ in 0..entry_count {
         let item_id = if version == 0 {
             be_u16(src)?.into()
@@ -1238,6 +3134,7 @@ fn read_ipma<T: Read>(src: &mut BMFFBox<T>) -> Result<TryVec<Association>> {
             be_u32(src)?
         };
         let association_count = src.read_u8()?;
+        ctx.allocate(u64::from(association_count) * core::mem::size_of::<Association>().to_u64())?;
         for _ in 0..association_count {
             let num_association_bytes = if flags & 1 == 1 { 2 } else { 1 };
             let association = &mut [0; 2][..num_association_bytes];
@@ -1255,30 +3152,74 @@ fn read_ipma<T: Read>(src: &mut BMFFBox<T>) -> Result<TryVec<Association>> {
     Ok(associations)
 }
 
-fn read_ipco<T: Read>(src: &mut BMFFBox<T>) -> Result<TryVec<ItemProperty>> {
+fn read_ipco<T: ByteSource>(src: &mut BMFFBox<T>, ctx: &mut ParseContext) -> Result<TryVec<ItemProperty>> {
     let mut properties = TryVec::new();
 
     let mut iter = src.box_iter();
     while let Some(mut b) = iter.next_box()? {
-        // Must push for every property to have correct index for them
-        properties.push(match b.head.name {
-            BoxType::PixelInformationBox => ItemProperty::Channels(read_pixi(&mut b)?),
-            BoxType::AuxiliaryTypeProperty => ItemProperty::AuxiliaryType(read_auxc(&mut b)?),
-            BoxType::ContentLightLevelBox => ItemProperty::ContentLightLevel(read_clli(&mut b)?),
-            BoxType::MasteringDisplayColourVolumeBox => ItemProperty::MasteringDisplayColourVolume(read_mdcv(&mut b)?),
-            _ => {
-                skip_box_remain(&mut b)?;
-                ItemProperty::Unsupported
-            },
+        ctx.in_box(&mut b, |b, ctx| {
+            if matches!(b.head.name, BoxType::UnknownBox(_)) {
+                ctx.warn(b, WarningCode::IgnoredBox, "unknown property has been skipped")?;
+            }
+            if b.head.name == BoxType::AV1CodecConfigurationBox && ctx.options.max_dimensions.is_some() {
+                check_av1c_dimensions(b, ctx)?;
+            }
+            let property = match read_ipco_child(b) {
+                Ok(property) => property,
+                // Readers should ignore properties with versions they don't know
+                Err(Error::Unsupported(UnsupportedFeature::BoxVersion { .. })) if !ctx.is_strict() => {
+                    ctx.warn(b, WarningCode::UnexpectedFlags, "property with unsupported version has been ignored")?;
+                    ItemProperty::Unsupported
+                },
+                Err(Error::InvalidData(_) | Error::Unsupported(_) | Error::UnexpectedEOF) if ctx.is_lenient() => {
+                    ctx.warn(b, WarningCode::InvalidProperty, "invalid property has been ignored")?;
+                    ItemProperty::Unsupported
+                },
+                Err(e) => return Err(e),
+            };
+            if b.bytes_left() > 0 {
+                if property != ItemProperty::Unsupported {
+                    ctx.warn(b, WarningCode::UnreadBoxContent, "property box has unexpected extra data")?;
+                }
+                skip_box_remain(b)?;
+            }
+            // Must push for every property to have correct index for them
+            properties.push(property)?;
+            Ok(())
         })?;
     }
     Ok(properties)
 }
 
-fn read_pixi<T: Read>(src: &mut BMFFBox<T>) -> Result<ArrayVec<u8, 16>> {
+/// Checks the sequence header in `configOBUs` before any item data is read
+fn check_av1c_dimensions<T: ByteSource>(b: &mut BMFFBox<T>, ctx: &mut ParseContext) -> Result<()> {
+    // marker, version, profile, level, etc. take 4 bytes
+    if b.bytes_left() <= 4 {
+        return Ok(());
+    }
+    ctx.allocate(b.bytes_left())?;
+    let content = b.read_into_try_vec()?;
+    ctx.check_av1_dimensions(content.get(4..).unwrap_or_default(), None)
+}
+
+fn read_ipco_child<T: ByteSource>(b: &mut BMFFBox<T>) -> Result<ItemProperty> {
+    Ok(match b.head.name {
+        BoxType::PixelInformationBox => ItemProperty::Channels(read_pixi(b)?),
+        BoxType::AuxiliaryTypeProperty => ItemProperty::AuxiliaryType(read_auxc(b)?),
+        BoxType::ContentLightLevelBox => ItemProperty::ContentLightLevel(read_clli(b)?),
+        BoxType::MasteringDisplayColourVolumeBox => ItemProperty::MasteringDisplayColourVolume(read_mdcv(b)?),
+        BoxType::ImageSpatialExtentsProperty => ItemProperty::ImageSpatialExtents(read_ispe(b)?),
+        _ => {
+            skip_box_remain(b)?;
+            ItemProperty::Unsupported
+        },
+    })
+}
+
+fn read_pixi<T: ByteSource>(src: &mut BMFFBox<T>) -> Result<ArrayVec<u8, 16>> {
     let version = read_fullbox_version_no_flags(src)?;
     if version != 0 {
-        return Err(Error::Unsupported("pixi version"));
+        return Err(Error::Unsupported(UnsupportedFeature::BoxVersion { box_type: *b"pixi", version }));
     }
 
     let num_channels = usize::from(src.read_u8()?);
@@ -1319,10 +3260,10 @@ impl TryClone for AuxiliaryTypeProperty {
     }
 }
 
-fn read_auxc<T: Read>(src: &mut BMFFBox<T>) -> Result<AuxiliaryTypeProperty> {
+fn read_auxc<T: ByteSource>(src: &mut BMFFBox<T>) -> Result<AuxiliaryTypeProperty> {
     let version = read_fullbox_version_no_flags(src)?;
     if version != 0 {
-        return Err(Error::Unsupported("auxC version"));
+        return Err(Error::Unsupported(UnsupportedFeature::BoxVersion { box_type: *b"auxC", version }));
     }
 
     let aux_data = src.read_into_try_vec()?;
@@ -1332,7 +3273,7 @@ fn read_auxc<T: Read>(src: &mut BMFFBox<T>) -> Result<AuxiliaryTypeProperty> {
 
 /// Parse a Content Light Level Information property box (`clli`).
 /// See ISOBMFF § 12.1.5 / CEA-861.3. NOT a FullBox.
-fn read_clli<T: Read>(src: &mut BMFFBox<T>) -> Result<ContentLightLevel> {
+fn read_clli<T: ByteSource>(src: &mut BMFFBox<T>) -> Result<ContentLightLevel> {
     let max_content_light_level = be_u16(src)?;
     let max_pic_average_light_level = be_u16(src)?;
     skip_box_remain(src)?;
@@ -1344,7 +3285,7 @@ fn read_clli<T: Read>(src: &mut BMFFBox<T>) -> Result<ContentLightLevel> {
 
 /// Parse a Mastering Display Colour Volume property box (`mdcv`).
 /// See ISOBMFF § 12.1.5 / SMPTE ST 2086. NOT a FullBox.
-fn read_mdcv<T: Read>(src: &mut BMFFBox<T>) -> Result<MasteringDisplayColourVolume> {
+fn read_mdcv<T: ByteSource>(src: &mut BMFFBox<T>) -> Result<MasteringDisplayColourVolume> {
     let primaries = [
         (be_u16(src)?, be_u16(src)?),
         (be_u16(src)?, be_u16(src)?),
@@ -1362,11 +3303,24 @@ fn read_mdcv<T: Read>(src: &mut BMFFBox<T>) -> Result<MasteringDisplayColourVolu
     })
 }
 
+/// Parse an Image Spatial Extents property box (`ispe`).
+/// See ISO 23008-12:2017 § 6.5.3
+fn read_ispe<T: ByteSource>(src: &mut BMFFBox<T>) -> Result<ImageSpatialExtents> {
+    let (version, _) = read_fullbox_extra(src)?;
+    if version != 0 {
+        return Err(Error::Unsupported(UnsupportedFeature::BoxVersion { box_type: *b"ispe", version }));
+    }
+    let width = be_u32(src)?;
+    let height = be_u32(src)?;
+    Ok(ImageSpatialExtents { width, height })
+}
+
 /// Parse an item location box inside a meta box
 /// See ISO 14496-12:2015 § 8.11.3
-fn read_iloc<T: Read>(src: &mut BMFFBox<T>) -> Result<TryVec<ItemLocationBoxItem>> {
+fn read_iloc<T: ByteSource>(src: &mut BMFFBox<T>, ctx: &mut ParseContext) -> Result<TryVec<ItemLocationBoxItem>> {
     let version: IlocVersion = read_fullbox_version_no_flags(src)?.try_into()?;
 
+    ctx.allocate(src.bytes_left())?;
     let iloc = src.read_into_try_vec()?;
     let mut iloc = BitReader::new(&iloc);
 
@@ -1387,6 +3341,8 @@ fn read_iloc<T: Read>(src: &mut BMFFBox<T>) -> Result<TryVec<ItemLocationBoxItem
         IlocVersion::Two => iloc.read_u32(32)?,
     };
 
+    ctx.check_item_count(item_count)?;
+    ctx.allocate(u64::from(item_count) * core::mem::size_of::<ItemLocationBoxItem>().to_u64())?;
     let mut items = TryVec::with_capacity(item_count.to_usize())?;
 
     for _ in 0..item_count {
@@ -1407,7 +3363,7 @@ fn read_iloc<T: Read>(src: &mut BMFFBox<T>) -> Result<TryVec<ItemLocationBoxItem
                 match iloc.read_u16(4)? {
                     0 => ConstructionMethod::File,
                     1 => ConstructionMethod::Idat,
-                    2 => return Err(Error::Unsupported("construction_method 'item_offset' is not supported")),
+                    2 => return Err(Error::Unsupported(UnsupportedFeature::ConstructionMethod(ConstructionMethod::Item as u8))),
                     _ => return Err(Error::InvalidData("construction_method is taken from the set 0, 1 or 2 per ISO 14496-12:2015 § 8.11.3.3")),
                 }
             },
@@ -1416,7 +3372,7 @@ fn read_iloc<T: Read>(src: &mut BMFFBox<T>) -> Result<TryVec<ItemLocationBoxItem
         let data_reference_index = iloc.read_u16(16)?;
 
         if data_reference_index != 0 {
-            return Err(Error::Unsupported("external file references (iloc.data_reference_index != 0) are not supported"));
+            return Err(Error::Unsupported(UnsupportedFeature::ExternalReference));
         }
 
         let base_offset = iloc.read_u64(base_offset_size.to_bits())?;
@@ -1426,6 +3382,10 @@ fn read_iloc<T: Read>(src: &mut BMFFBox<T>) -> Result<TryVec<ItemLocationBoxItem
             return Err(Error::InvalidData("extent_count must have a value 1 or greater per ISO 14496-12:2015 § 8.11.3.3"));
         }
 
+        if ctx.options.max_extents_per_item.is_some_and(|max| extent_count > max) {
+            return Err(Error::LimitExceeded("max_extents_per_item"));
+        }
+        ctx.allocate(u64::from(extent_count) * core::mem::size_of::<ItemLocationBoxExtent>().to_u64())?;
         let mut extents = TryVec::with_capacity(extent_count.to_usize())?;
 
         for _ in 0..extent_count {
@@ -1474,7 +3434,7 @@ fn read_iloc<T: Read>(src: &mut BMFFBox<T>) -> Result<TryVec<ItemLocationBoxItem
 
 /// Parse an ftyp box.
 /// See ISO 14496-12:2015 § 4.3
-fn read_ftyp<T: Read>(src: &mut BMFFBox<T>) -> Result<FileTypeBox> {
+fn read_ftyp<T: ByteSource>(src: &mut BMFFBox<T>) -> Result<FileType> {
     let major = be_u32(src)?;
     let minor = be_u32(src)?;
     let bytes_left = src.bytes_left();
@@ -1485,10 +3445,10 @@ fn read_ftyp<T: Read>(src: &mut BMFFBox<T>) -> Result<FileTypeBox> {
     let brand_count = bytes_left / 4;
     let mut brands = TryVec::with_capacity(brand_count.try_into()?)?;
     for _ in 0..brand_count {
-        brands.push(be_u32(src)?.into())?;
+        brands.push(be_u32(src)?.to_be_bytes())?;
     }
-    Ok(FileTypeBox {
-        major_brand: From::from(major),
+    Ok(FileType {
+        major_brand: major.to_be_bytes(),
         minor_version: minor,
         compatible_brands: brands,
     })
@@ -1506,19 +3466,25 @@ fn check_parser_state<T>(left: &Take<T>) -> Result<(), Error> {
 }
 
 /// Skip a number of bytes that we don't care to parse.
-fn skip<T: Read>(src: &mut T, bytes: u64) -> Result<()> {
-    std::io::copy(&mut src.take(bytes), &mut std::io::sink())?;
+fn skip<T: ByteSource>(src: &mut T, bytes: u64) -> Result<()> {
+    src.skip_bytes(bytes)?;
     Ok(())
 }
 
-fn be_u16<T: ReadBytesExt>(src: &mut T) -> Result<u16> {
-    src.read_u16::<byteorder::BigEndian>().map_err(From::from)
+fn be_u16<T: ByteSource>(src: &mut T) -> Result<u16> {
+    let mut buf = [0; 2];
+    src.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
 }
 
-fn be_u32<T: ReadBytesExt>(src: &mut T) -> Result<u32> {
-    src.read_u32::<byteorder::BigEndian>().map_err(From::from)
+fn be_u32<T: ByteSource>(src: &mut T) -> Result<u32> {
+    let mut buf = [0; 4];
+    src.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
 }
 
-fn be_u64<T: ReadBytesExt>(src: &mut T) -> Result<u64> {
-    src.read_u64::<byteorder::BigEndian>().map_err(From::from)
+fn be_u64<T: ByteSource>(src: &mut T) -> Result<u64> {
+    let mut buf = [0; 8];
+    src.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
 }
@@ -1,23 +1,349 @@
 #![allow(unused)]
 #![allow(bad_style)]
 
-use crate::{Error, Result};
+use crate::{ContentLightLevel, Error, MasteringDisplayColourVolume, Result, TryVec, UnsupportedFeature};
 
+use arrayvec::ArrayVec;
 use bitreader::BitReader;
-use std::num::{NonZeroU8, NonZeroU32};
+use core::num::{NonZeroU8, NonZeroU32};
 
+/// Type of an OBU. See AV1 spec § 6.2.2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObuType {
+    SequenceHeader,
+    TemporalDelimiter,
+    FrameHeader,
+    TileGroup,
+    Metadata,
+    Frame,
+    RedundantFrameHeader,
+    TileList,
+    Padding,
+    /// Reserved values 0 and 9-14
+    Reserved(u8),
+}
+
+impl From<u8> for ObuType {
+    fn from(obu_type: u8) -> Self {
+        match obu_type {
+            1 => Self::SequenceHeader,
+            2 => Self::TemporalDelimiter,
+            3 => Self::FrameHeader,
+            4 => Self::TileGroup,
+            5 => Self::Metadata,
+            6 => Self::Frame,
+            7 => Self::RedundantFrameHeader,
+            8 => Self::TileList,
+            15 => Self::Padding,
+            other => Self::Reserved(other),
+        }
+    }
+}
+
+impl From<ObuType> for u8 {
+    fn from(obu_type: ObuType) -> Self {
+        match obu_type {
+            ObuType::SequenceHeader => 1,
+            ObuType::TemporalDelimiter => 2,
+            ObuType::FrameHeader => 3,
+            ObuType::TileGroup => 4,
+            ObuType::Metadata => 5,
+            ObuType::Frame => 6,
+            ObuType::RedundantFrameHeader => 7,
+            ObuType::TileList => 8,
+            ObuType::Padding => 15,
+            ObuType::Reserved(other) => other,
+        }
+    }
+}
+
+/// `obu_extension_header()`. See AV1 spec § 5.3.3
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObuExtension {
+    pub temporal_id: u8,
+    pub spatial_id: u8,
+}
+
+/// A single OBU borrowed from an AV1 bitstream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Obu<'a> {
+    pub obu_type: ObuType,
+    /// Present if `obu_extension_flag` is set
+    pub extension: Option<ObuExtension>,
+    /// If `false`, the OBU extends to the end of the bitstream
+    pub has_size_field: bool,
+    /// The OBU without its header and size field
+    pub payload: &'a [u8],
+    /// The whole OBU, including its header. Can be copied as-is to another bitstream.
+    pub data: &'a [u8],
+}
+
+/// Iterates OBUs in an AV1 bitstream (such as [`AvifData::primary_item`](crate::AvifData::primary_item)) without copying.
+///
+/// Stops after the first error.
 #[derive(Debug, Clone)]
-struct Header {
-    obu_size: usize,
-    is_sequence_header: bool,
+pub struct ObuIter<'a> {
+    data: &'a [u8],
+    /// Remaining bytes of the current `temporal_unit()` and `frame_unit()`, if the bitstream is in the Annex B format
+    annexb: Option<(&'a [u8], &'a [u8])>,
+}
+
+impl<'a> ObuIter<'a> {
+    /// For the low-overhead bitstream format (AV1 spec § 5.2), which is used in AVIF files
+    #[must_use]
+    pub fn new(obu_bitstream: &'a [u8]) -> Self {
+        Self { data: obu_bitstream, annexb: None }
+    }
+
+    /// For the length-delimited bitstream format (AV1 spec Annex B)
+    #[must_use]
+    pub fn new_annexb(obu_bitstream: &'a [u8]) -> Self {
+        Self { data: obu_bitstream, annexb: Some((&[], &[])) }
+    }
+
+    /// Uses [`is_annexb`] to pick the bitstream format
+    #[must_use]
+    pub fn detect(obu_bitstream: &'a [u8]) -> Self {
+        if is_annexb(obu_bitstream) {
+            Self::new_annexb(obu_bitstream)
+        } else {
+            Self::new(obu_bitstream)
+        }
+    }
+
+    fn next_annexb_obu(&mut self) -> Option<Result<Obu<'a>>> {
+        let (mut temporal_unit, mut frame_unit) = self.annexb?;
+        while frame_unit.is_empty() {
+            if temporal_unit.is_empty() {
+                if self.data.is_empty() {
+                    return None;
+                }
+                temporal_unit = match take_sized(&mut self.data) {
+                    Ok(t) => t,
+                    Err(e) => return Some(Err(e)),
+                };
+            }
+            frame_unit = match take_sized(&mut temporal_unit) {
+                Ok(f) => f,
+                Err(e) => return Some(Err(e)),
+            };
+        }
+        let res = take_sized(&mut frame_unit).and_then(|mut obu_data| {
+            let obu = next_obu(&mut obu_data)?;
+            if !obu_data.is_empty() {
+                return Err(Error::InvalidData("obu_length mismatch"));
+            }
+            Ok(obu)
+        });
+        self.annexb = Some((temporal_unit, frame_unit));
+        Some(res)
+    }
+}
+
+impl<'a> Iterator for ObuIter<'a> {
+    type Item = Result<Obu<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let res = if self.annexb.is_some() {
+            self.next_annexb_obu()?
+        } else {
+            if self.data.is_empty() {
+                return None;
+            }
+            next_obu(&mut self.data)
+        };
+        if res.is_err() {
+            self.data = &[];
+            self.annexb = None;
+        }
+        Some(res)
+    }
+}
+
+/// Guesses whether the bitstream is in the Annex B format rather than the low-overhead format.
+///
+/// A bitstream is considered Annex B only if it doesn't start with a temporal delimiter or a sequence header OBU,
+/// and its first temporal unit has consistent sizes.
+#[must_use]
+pub fn is_annexb(obu_bitstream: &[u8]) -> bool {
+    let starts_low_overhead = ObuIter::new(obu_bitstream).next().is_some_and(|obu| match obu {
+        Ok(obu) if obu.has_size_field => match obu.obu_type {
+            ObuType::TemporalDelimiter => obu.payload.is_empty(),
+            ObuType::SequenceHeader => SequenceHeaderObu::read(obu.payload).is_ok(),
+            _ => false,
+        },
+        _ => false,
+    });
+    if starts_low_overhead {
+        return false;
+    }
+
+    let mut data = obu_bitstream;
+    let Ok(mut temporal_unit) = take_sized(&mut data) else { return false };
+    if temporal_unit.is_empty() {
+        return false;
+    }
+    let mut first_obu = true;
+    while !temporal_unit.is_empty() {
+        let Ok(mut frame_unit) = take_sized(&mut temporal_unit) else { return false };
+        while !frame_unit.is_empty() {
+            let Ok(mut obu_data) = take_sized(&mut frame_unit) else { return false };
+            match next_obu(&mut obu_data) {
+                Ok(obu) if obu_data.is_empty() && !matches!(obu.obu_type, ObuType::Reserved(_)) => {
+                    if first_obu && !matches!(obu.obu_type, ObuType::TemporalDelimiter | ObuType::SequenceHeader) {
+                        return false;
+                    }
+                    first_obu = false;
+                },
+                _ => return false,
+            }
+        }
+    }
+    true
+}
+
+/// Converts an Annex B bitstream to the low-overhead format used in AVIF files.
+///
+/// All OBUs in the output have `obu_has_size_field` set.
+pub fn annexb_to_low_overhead(obu_bitstream: &[u8]) -> Result<TryVec<u8>> {
+    let mut out = TryVec::with_capacity(obu_bitstream.len())?;
+    for obu in ObuIter::new_annexb(obu_bitstream) {
+        let obu = obu?;
+        out.push(obu.data[0] | 0b010)?;
+        if obu.extension.is_some() {
+            out.push(obu.data[1])?;
+        }
+        write_leb128(&mut out, obu.payload.len().try_into()?)?;
+        out.extend_from_slice(obu.payload)?;
+    }
+    Ok(out)
+}
+
+/// Converts a low-overhead bitstream to the Annex B format.
+///
+/// Temporal units are split at temporal delimiter OBUs, and frame units at frame header OBUs.
+/// OBUs in the output don't have `obu_has_size_field` set.
+pub fn low_overhead_to_annexb(obu_bitstream: &[u8]) -> Result<TryVec<u8>> {
+    let mut out = TryVec::with_capacity(obu_bitstream.len() + 16)?;
+    // OBUs of the current temporal unit, and the index of the first OBU of each of its frame units
+    let mut temporal_unit = TryVec::new();
+    let mut frame_unit_starts = TryVec::new();
+    let mut frame_unit_has_frame = false;
+    for obu in ObuIter::new(obu_bitstream) {
+        let obu = obu?;
+        if obu.obu_type == ObuType::TemporalDelimiter && !temporal_unit.is_empty() {
+            write_annexb_temporal_unit(&mut out, &temporal_unit, &frame_unit_starts)?;
+            temporal_unit.clear();
+            frame_unit_starts.clear();
+            frame_unit_has_frame = false;
+        }
+        let is_frame = matches!(obu.obu_type, ObuType::FrameHeader | ObuType::Frame);
+        if frame_unit_starts.is_empty() || (is_frame && frame_unit_has_frame) {
+            frame_unit_starts.push(temporal_unit.len())?;
+            frame_unit_has_frame = false;
+        }
+        frame_unit_has_frame |= is_frame;
+        temporal_unit.push(obu)?;
+    }
+    if !temporal_unit.is_empty() {
+        write_annexb_temporal_unit(&mut out, &temporal_unit, &frame_unit_starts)?;
+    }
+    Ok(out)
+}
+
+fn write_annexb_temporal_unit(out: &mut TryVec<u8>, obus: &[Obu<'_>], frame_unit_starts: &[usize]) -> Result<()> {
+    // Header and payload, without the size field
+    let obu_length = |obu: &Obu<'_>| 1 + usize::from(obu.extension.is_some()) + obu.payload.len();
+    let frame_units = frame_unit_starts.iter().enumerate().map(|(i, &start)| {
+        &obus[start..frame_unit_starts.get(i + 1).copied().unwrap_or(obus.len())]
+    });
+    let frame_unit_size = |frame_unit: &[Obu<'_>]| -> usize {
+        frame_unit.iter().map(|obu| {
+            let len = obu_length(obu);
+            leb128_len(len as u64) + len
+        }).sum()
+    };
+
+    let temporal_unit_size: usize = frame_units.clone().map(|f| {
+        let len = frame_unit_size(f);
+        leb128_len(len as u64) + len
+    }).sum();
+    write_leb128(out, temporal_unit_size as u64)?;
+    for frame_unit in frame_units {
+        write_leb128(out, frame_unit_size(frame_unit) as u64)?;
+        for obu in frame_unit {
+            write_leb128(out, obu_length(obu) as u64)?;
+            out.push(obu.data[0] & !0b010)?;
+            if obu.extension.is_some() {
+                out.push(obu.data[1])?;
+            }
+            out.extend_from_slice(obu.payload)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a leb128-prefixed slice
+fn take_sized<'a>(data: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let size: usize = read_leb128(data)?
+        .try_into()
+        .map_err(|_| Error::UnexpectedEOF)?;
+    let (sized, rest) = data.split_at_checked(size).ok_or(Error::UnexpectedEOF)?;
+    *data = rest;
+    Ok(sized)
+}
+
+/// Reads a leb128-encoded number, up to 64 bits
+fn read_leb128(data: &mut &[u8]) -> Result<u64> {
+    let mut val = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data.split_first().ok_or(Error::InvalidData("leb"))?;
+        *data = rest;
+        let bits = u64::from(byte & 0x7F);
+        if bits << shift >> shift != bits {
+            return Err(Error::InvalidData("leb"));
+        }
+        val |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(val);
+        }
+    }
+    Err(Error::InvalidData("leb"))
+}
+
+fn leb128_len(mut val: u64) -> usize {
+    let mut len = 1;
+    while val >= 0x80 {
+        val >>= 7;
+        len += 1;
+    }
+    len
+}
+
+fn write_leb128(out: &mut TryVec<u8>, mut val: u64) -> Result<()> {
+    while val >= 0x80 {
+        out.push((val as u8 & 0x7F) | 0x80)?;
+        val >>= 7;
+    }
+    out.push(val as u8)?;
+    Ok(())
 }
 
+const METADATA_TYPE_HDR_CLL: u64 = 1;
+const METADATA_TYPE_HDR_MDCV: u64 = 2;
+const METADATA_TYPE_SCALABILITY: u64 = 3;
+const METADATA_TYPE_ITUT_T35: u64 = 4;
+
+const SCALABILITY_SS: u8 = 14;
+
 fn get_byte(data: &mut &[u8]) -> Result<u8> {
     let (&b, rest) = (*data).split_first().ok_or(Error::UnexpectedEOF)?;
     *data = rest;
     Ok(b)
 }
 
+const KEY_FRAME: u8 = 0;
+
 const INTRA_FRAME: usize = 0;
 const LAST_FRAME: usize = 1;
 const LAST2_FRAME: usize = 2;
@@ -27,19 +353,328 @@ const BWDREF_FRAME: usize = 5;
 const ALTREF2_FRAME: usize = 6;
 const ALTREF_FRAME: usize = 7;
 
-pub(crate) fn parse_obu(mut data: &[u8]) -> Result<SequenceHeaderObu> {
-    while !data.is_empty() {
-        let h = obu_header(&mut data)?;
-        let mut remaining_data = data.get(..h.obu_size).ok_or(Error::UnexpectedEOF)?;
-        data = &data[h.obu_size..];
-
-        if h.is_sequence_header {
-            return SequenceHeaderObu::read(remaining_data);
+pub(crate) fn parse_obu(data: &[u8]) -> Result<SequenceHeaderObu> {
+    for obu in ObuIter::detect(data) {
+        let obu = obu?;
+        if obu.obu_type == ObuType::SequenceHeader {
+            return SequenceHeaderObu::read(obu.payload);
         }
     }
     Err(Error::UnexpectedEOF)
 }
 
+/// Collects payloads of all `OBU_METADATA` OBUs
+pub(crate) fn parse_metadata_obus(data: &[u8]) -> Result<TryVec<ObuMetadata<'_>>> {
+    let mut metadata = TryVec::new();
+    for obu in ObuIter::detect(data) {
+        let obu = obu?;
+        if obu.obu_type == ObuType::Metadata {
+            metadata.push(ObuMetadata::read(obu.payload)?)?;
+        }
+    }
+    Ok(metadata)
+}
+
+/// Payload of an AV1 metadata OBU. See AV1 spec § 5.8.1
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ObuMetadata<'a> {
+    /// `METADATA_TYPE_HDR_CLL`
+    ContentLightLevel(ContentLightLevel),
+    /// `METADATA_TYPE_HDR_MDCV`, converted to the units used by the `mdcv` box
+    MasteringDisplay(MasteringDisplayColourVolume),
+    /// `METADATA_TYPE_SCALABILITY`
+    Scalability(ScalabilityMetadata<'a>),
+    /// `METADATA_TYPE_ITUT_T35`, e.g. HDR10+ or Dolby Vision
+    ItuTT35(ItuTT35Metadata<'a>),
+    /// Timecode or a reserved/unregistered type
+    Other {
+        metadata_type: u64,
+        /// Raw payload, including trailing bits
+        payload: &'a [u8],
+    },
+}
+
+/// ITU-T T.35 metadata. See AV1 spec § 5.8.2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItuTT35Metadata<'a> {
+    pub country_code: u8,
+    /// Only present if `country_code` is `0xFF`
+    pub country_code_extension: Option<u8>,
+    /// Bytes following the country code, without the OBU's trailing bits.
+    ///
+    /// For HDR10+ and Dolby Vision this starts with the terminal provider code.
+    pub payload: &'a [u8],
+}
+
+/// See AV1 spec § 5.8.5
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScalabilityMetadata<'a> {
+    pub scalability_mode_idc: u8,
+    /// Explicit `scalability_structure()`. Only present if `scalability_mode_idc` is `SCALABILITY_SS` (14)
+    pub structure: Option<ScalabilityStructure<'a>>,
+}
+
+/// See AV1 spec § 5.8.6
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScalabilityStructure<'a> {
+    /// Between 1 and 4 layers
+    pub spatial_layers: ArrayVec<SpatialLayer, 4>,
+    temporal_group: Option<&'a [u8]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpatialLayer {
+    /// Width and height, if `spatial_layer_dimensions_present_flag` is set
+    pub dimensions: Option<(u16, u16)>,
+    /// `spatial_layer_ref_id`, if `spatial_layer_description_present_flag` is set
+    pub ref_id: Option<u8>,
+}
+
+/// Entry of the temporal group description of [`ScalabilityStructure`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemporalGroupEntry {
+    pub temporal_id: u8,
+    pub temporal_switching_up_point: bool,
+    pub spatial_switching_up_point: bool,
+    pub ref_pic_diff: ArrayVec<u8, 7>,
+}
+
+impl ScalabilityStructure<'_> {
+    /// Temporal group description, if `temporal_group_description_present_flag` is set
+    pub fn temporal_group(&self) -> Option<Result<TryVec<TemporalGroupEntry>>> {
+        let data = self.temporal_group?;
+        Some((|| {
+            let mut b = BitReader::new(data);
+            let temporal_group_size = b.read_u8(8)?;
+            let mut entries = TryVec::with_capacity(temporal_group_size.into())?;
+            for _ in 0..temporal_group_size {
+                let temporal_id = b.read_u8(3)?;
+                let temporal_switching_up_point = b.read_bool()?;
+                let spatial_switching_up_point = b.read_bool()?;
+                let ref_cnt = b.read_u8(3)?;
+                let mut ref_pic_diff = ArrayVec::new();
+                for _ in 0..ref_cnt {
+                    ref_pic_diff.push(b.read_u8(8)?);
+                }
+                entries.push(TemporalGroupEntry {
+                    temporal_id,
+                    temporal_switching_up_point,
+                    spatial_switching_up_point,
+                    ref_pic_diff,
+                })?;
+            }
+            Ok(entries)
+        })())
+    }
+}
+
+impl<'a> ObuMetadata<'a> {
+    fn read(mut data: &'a [u8]) -> Result<Self> {
+        let metadata_type = read_leb128(&mut data)?;
+        let mut b = BitReader::new(data);
+        Ok(match metadata_type {
+            METADATA_TYPE_HDR_CLL => Self::ContentLightLevel(ContentLightLevel {
+                max_content_light_level: b.read_u16(16)?,
+                max_pic_average_light_level: b.read_u16(16)?,
+            }),
+            METADATA_TYPE_HDR_MDCV => {
+                // AV1 uses 0.16 fixed-point chromaticities in R, G, B order
+                let mut primaries = [(0, 0); 3];
+                for p in &mut primaries {
+                    *p = (chromaticity(b.read_u16(16)?), chromaticity(b.read_u16(16)?));
+                }
+                let [red, green, blue] = primaries;
+                let white_point = (chromaticity(b.read_u16(16)?), chromaticity(b.read_u16(16)?));
+                // 24.8 and 18.14 fixed-point cd/m²
+                let luminance_max = u64::from(b.read_u32(32)?);
+                let luminance_min = u64::from(b.read_u32(32)?);
+                Self::MasteringDisplay(MasteringDisplayColourVolume {
+                    primaries: [green, blue, red],
+                    white_point,
+                    max_luminance: ((luminance_max * 10000 + 128) >> 8).try_into().unwrap_or(u32::MAX),
+                    min_luminance: ((luminance_min * 10000 + 8192) >> 14).try_into().unwrap_or(u32::MAX),
+                })
+            },
+            METADATA_TYPE_SCALABILITY => {
+                let scalability_mode_idc = b.read_u8(8)?;
+                let structure = if scalability_mode_idc == SCALABILITY_SS {
+                    let spatial_layers_cnt = 1 + b.read_u8(2)?;
+                    let spatial_layer_dimensions_present_flag = b.read_bool()?;
+                    let spatial_layer_description_present_flag = b.read_bool()?;
+                    let temporal_group_description_present_flag = b.read_bool()?;
+                    let _scalability_structure_reserved_3bits = b.read_u8(3)?;
+                    let mut spatial_layers: ArrayVec<_, 4> = (0..spatial_layers_cnt)
+                        .map(|_| SpatialLayer { dimensions: None, ref_id: None })
+                        .collect();
+                    if spatial_layer_dimensions_present_flag {
+                        for layer in &mut spatial_layers {
+                            layer.dimensions = Some((1 + b.read_u16(16)?, 1 + b.read_u16(16)?));
+                        }
+                    }
+                    if spatial_layer_description_present_flag {
+                        for layer in &mut spatial_layers {
+                            layer.ref_id = Some(b.read_u8(8)?);
+                        }
+                    }
+                    let temporal_group = if temporal_group_description_present_flag {
+                        let pos = (b.position() / 8).try_into()?;
+                        Some(data.get(pos..).ok_or(Error::UnexpectedEOF)?)
+                    } else {
+                        None
+                    };
+                    Some(ScalabilityStructure { spatial_layers, temporal_group })
+                } else {
+                    None
+                };
+                Self::Scalability(ScalabilityMetadata { scalability_mode_idc, structure })
+            },
+            METADATA_TYPE_ITUT_T35 => {
+                let (&country_code, mut rest) = data.split_first().ok_or(Error::UnexpectedEOF)?;
+                let country_code_extension = if country_code == 0xFF {
+                    Some(get_byte(&mut rest)?)
+                } else {
+                    None
+                };
+                Self::ItuTT35(ItuTT35Metadata {
+                    country_code,
+                    country_code_extension,
+                    payload: strip_trailing_bits(rest),
+                })
+            },
+            _ => Self::Other { metadata_type, payload: data },
+        })
+    }
+}
+
+/// Converts AV1's 0.16 fixed-point to `mdcv`'s units of 0.00002
+fn chromaticity(val: u16) -> u16 {
+    ((u32::from(val) * 50000 + (1 << 15)) >> 16) as u16
+}
+
+/// Removes `trailing_bits()` from a byte-aligned payload
+fn strip_trailing_bits(data: &[u8]) -> &[u8] {
+    let end = data.iter().rposition(|&b| b != 0).unwrap_or(0);
+    &data[..end]
+}
+
+/// A deviation of an AV1 image item from AVIF's still image requirements. See AVIF spec § 2.1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StillImageViolation {
+    /// No sequence header OBU
+    MissingSequenceHeader,
+    /// Sequence header OBUs in the same item differ
+    MismatchedSequenceHeaders,
+    /// More than one temporal delimiter
+    MultipleTemporalUnits { count: usize },
+    /// No frame or frame header OBU
+    MissingFrame,
+    /// More than one frame or frame header OBU
+    MultipleFrames { count: usize },
+    /// The frame isn't a key frame, or shows an existing frame
+    NonKeyFrame,
+    /// The frame has `show_frame` = 0
+    FrameNotShown,
+    /// Recommended: the sequence header should have `still_picture` set
+    NotStillPicture,
+    /// Recommended: the sequence header should have `reduced_still_picture_header` set
+    NotReducedStillPictureHeader,
+}
+
+impl StillImageViolation {
+    /// `true` if it's only a recommendation of the spec, rather than a requirement
+    #[must_use]
+    pub fn is_recommendation(&self) -> bool {
+        matches!(self, Self::NotStillPicture | Self::NotReducedStillPictureHeader)
+    }
+}
+
+impl core::fmt::Display for StillImageViolation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::MissingSequenceHeader => f.write_str("missing sequence header OBU"),
+            Self::MismatchedSequenceHeaders => f.write_str("sequence header OBUs differ"),
+            Self::MultipleTemporalUnits { count } => write!(f, "{count} temporal units instead of one"),
+            Self::MissingFrame => f.write_str("missing frame OBU"),
+            Self::MultipleFrames { count } => write!(f, "{count} frames instead of one"),
+            Self::NonKeyFrame => f.write_str("frame is not a key frame"),
+            Self::FrameNotShown => f.write_str("frame is not shown"),
+            Self::NotStillPicture => f.write_str("still_picture is not set"),
+            Self::NotReducedStillPictureHeader => f.write_str("reduced_still_picture_header is not set"),
+        }
+    }
+}
+
+/// Checks that the bitstream is a single temporal unit with a sequence header and one shown key frame
+pub(crate) fn still_image_violations(data: &[u8]) -> Result<TryVec<StillImageViolation>> {
+    let mut violations = TryVec::new();
+    let mut sequence_header: Option<(&[u8], SequenceHeaderObu)> = None;
+    let mut temporal_units = 0;
+    let mut frames = 0;
+    let mut non_key_frame = false;
+    let mut not_shown = false;
+    let mut mismatched_sequence_headers = false;
+
+    for obu in ObuIter::detect(data) {
+        let obu = obu?;
+        match obu.obu_type {
+            ObuType::SequenceHeader => match &sequence_header {
+                Some((payload, _)) => mismatched_sequence_headers |= *payload != obu.payload,
+                None => sequence_header = Some((obu.payload, SequenceHeaderObu::read(obu.payload)?)),
+            },
+            ObuType::TemporalDelimiter => temporal_units += 1,
+            ObuType::FrameHeader | ObuType::Frame => {
+                frames += 1;
+                // Without a sequence header the frame header can't be interpreted
+                if let Some((_, seq)) = &sequence_header && !seq.reduced_still_picture_header {
+                    let mut b = BitReader::new(obu.payload);
+                    let show_existing_frame = b.read_bool()?;
+                    if show_existing_frame {
+                        non_key_frame = true;
+                    } else {
+                        let frame_type = b.read_u8(2)?;
+                        let show_frame = b.read_bool()?;
+                        non_key_frame |= frame_type != KEY_FRAME;
+                        not_shown |= !show_frame;
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+
+    match &sequence_header {
+        None => violations.push(StillImageViolation::MissingSequenceHeader)?,
+        Some((_, seq)) => {
+            if mismatched_sequence_headers {
+                violations.push(StillImageViolation::MismatchedSequenceHeaders)?;
+            }
+            if !seq.still_picture {
+                violations.push(StillImageViolation::NotStillPicture)?;
+            }
+            if !seq.reduced_still_picture_header {
+                violations.push(StillImageViolation::NotReducedStillPictureHeader)?;
+            }
+        },
+    }
+    if temporal_units > 1 {
+        violations.push(StillImageViolation::MultipleTemporalUnits { count: temporal_units })?;
+    }
+    match frames {
+        0 => violations.push(StillImageViolation::MissingFrame)?,
+        1 => {},
+        count => violations.push(StillImageViolation::MultipleFrames { count })?,
+    }
+    if non_key_frame {
+        violations.push(StillImageViolation::NonKeyFrame)?;
+    }
+    if not_shown {
+        violations.push(StillImageViolation::FrameNotShown)?;
+    }
+    Ok(violations)
+}
+
 impl SequenceHeaderObu {
     fn read(data: &[u8]) -> Result<Self> {
         let mut b = BitReader::new(data);
@@ -55,30 +690,35 @@ impl SequenceHeaderObu {
         let reduced_still_picture_header = b.read_bool()?;
 
         let decoder_model_info_present_flag = false;
+        let mut seq_level_idx_0 = 0;
         if reduced_still_picture_header {
             let timing_info_present_flag = 0;
             let initial_display_delay_present_flag = 0;
             let operating_points_cnt_minus_1 = 0;
             let operating_point_idc = 0; // [ 0 ]
             let seq_level_idx = b.read_u8(5)?;
+            seq_level_idx_0 = seq_level_idx;
             let seq_tier = 0; // [ 0 ]
             let decoder_model_present_for_this_op = 0; // [ 0 ]
             let initial_display_delay_present_for_this_op = 0; // [ 0 ]
         } else {
             let timing_info_present_flag = b.read_bool()?;
             if timing_info_present_flag {
-                return Err(Error::Unsupported("timing_info_present_flag"));
+                return Err(Error::Unsupported(UnsupportedFeature::Other("timing_info_present_flag")));
             }
             let initial_display_delay_present_flag = b.read_bool()?;
             let operating_points_cnt = 1 + b.read_u8(5)?;
 
-            for _ in 0..operating_points_cnt {
+            for i in 0..operating_points_cnt {
                 let operating_point_idc = b.read_u16(12)?;
                 let seq_level_idx = b.read_u8(5)?;
+                if i == 0 {
+                    seq_level_idx_0 = seq_level_idx;
+                }
                 let seq_tier = if seq_level_idx > 7 { b.read_bool()? } else { false };
                 let decoder_model_present_for_this_op = if decoder_model_info_present_flag {
                     b.read_bool()?;
-                    return Err(Error::Unsupported("decoder_model_info_present_flag"));
+                    return Err(Error::Unsupported(UnsupportedFeature::Other("decoder_model_info_present_flag")));
                 } else {
                     false
                 };
@@ -155,6 +795,7 @@ impl SequenceHeaderObu {
         Ok(Self {
             color,
             seq_profile,
+            seq_level_idx: seq_level_idx_0,
             still_picture,
             reduced_still_picture_header,
             max_frame_width,
@@ -188,6 +829,8 @@ pub(crate) struct SequenceHeaderObu {
     pub color: ColorConfig,
 
     pub seq_profile: u8,
+    /// Of the first operating point
+    pub seq_level_idx: u8,
     pub still_picture: bool,
     pub reduced_still_picture_header: bool,
 
@@ -314,31 +957,46 @@ fn color_config(b: &mut BitReader, seq_profile: u8) -> Result<ColorConfig> {
     })
 }
 
-fn obu_header(data: &mut &[u8]) -> Result<Header> {
-    let mut b = get_byte(data)?;
+fn next_obu<'a>(data: &mut &'a [u8]) -> Result<Obu<'a>> {
+    let start = *data;
+    let b = get_byte(data)?;
     if 0 != b & 0b1000_0000 {
         return Err(Error::InvalidData("not obu"));
     }
 
-    let is_sequence_header = 1 == (b >> 3);
+    let obu_type = ObuType::from((b >> 3) & 0b1111);
     let obu_extension_flag = 0 != (b & 0b100);
-    let obu_has_size_field = 0 != (b & 0b010);
+    let has_size_field = 0 != (b & 0b010);
 
-    if obu_extension_flag {
-        // obu_extension_header
-        let mut b = get_byte(data)?;
-    }
+    let extension = if obu_extension_flag {
+        let b = get_byte(data)?;
+        Some(ObuExtension {
+            temporal_id: b >> 5,
+            spatial_id: (b >> 3) & 0b11,
+        })
+    } else {
+        None
+    };
 
-    let obu_size = if obu_has_size_field {
-        leb128::read::unsigned(data)
-            .map_err(|_| Error::InvalidData("leb"))?
+    let obu_size = if has_size_field {
+        read_leb128(data)?
             .try_into()
             .map_err(|_| Error::UnexpectedEOF)?
     } else {
         data.len()
     };
 
-    Ok(Header { obu_size, is_sequence_header })
+    let payload = data.get(..obu_size).ok_or(Error::UnexpectedEOF)?;
+    *data = &data[obu_size..];
+    let header_size = start.len() - payload.len() - data.len();
+
+    Ok(Obu {
+        obu_type,
+        extension,
+        has_size_field,
+        payload,
+        data: &start[..header_size + obu_size],
+    })
 }
 
 const REFS_PER_FRAME: usize = 7; //   Number of reference frames that can be used for inter prediction
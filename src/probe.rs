@@ -0,0 +1,124 @@
+use crate::boxes::BoxType;
+use crate::{
+    read_box_header, read_ftyp, read_meta_boxes, slice_box, AvifInternalMeta, BoxHeader, FileType, ItemProperty, ParseContext,
+    ParseOptions, Strictness, ToU64,
+};
+
+/// Kind of file, see [`probe()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FileFormat {
+    /// `avif` brand
+    AvifStill,
+    /// `avis` brand without `avif` as the major brand
+    AvifSequence,
+    /// HEIF with HEVC, e.g. `heic` or `heix` brand
+    Heic,
+    /// Starts with an `ftyp` box, or another box type known to this crate
+    OtherIsobmff,
+    Unknown,
+}
+
+/// Result of [`probe()`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct Probe {
+    pub format: FileFormat,
+    /// `None` if the file doesn't start with a complete `ftyp` box
+    pub file_type: Option<FileType>,
+    /// Whether the primary item has an alpha auxiliary image. `None` if the `meta` box isn't in the given bytes.
+    pub has_alpha: Option<bool>,
+    /// Whether the primary item is a `grid` of tiles. `None` if the `meta` box isn't in the given bytes.
+    pub has_grid: Option<bool>,
+    /// Whether `ftyp` has an image sequence brand, such as `avis` or `msf1`
+    pub is_animated: Option<bool>,
+}
+
+const HEIC_BRANDS: [[u8; 4]; 8] = [*b"heic", *b"heix", *b"heim", *b"heis", *b"hevc", *b"hevx", *b"hevm", *b"hevs"];
+const SEQUENCE_BRANDS: [[u8; 4]; 6] = [*b"avis", *b"msf1", *b"hevc", *b"hevx", *b"hevm", *b"hevs"];
+const ALPHA_URNS: [&[u8]; 2] = [b"urn:mpeg:mpegB:cicp:systems:auxiliary:alpha", b"urn:mpeg:hevc:2015:auxid:1"];
+
+/// Guesses the file format from the beginning of the file, e.g. the first few hundred bytes.
+///
+/// Only `ftyp` and `meta` boxes are parsed, and nothing after the first `mdat` box is looked at.
+/// Never fails: damaged or cut off files give `Unknown` format or `None` fields.
+#[must_use]
+pub fn probe(data: &[u8]) -> Probe {
+    let mut probe = Probe { format: FileFormat::Unknown, file_type: None, has_alpha: None, has_grid: None, is_animated: None };
+
+    let mut offset = 0;
+    let mut first_box = true;
+    while let Some((head, content)) = data.get(offset..).and_then(|rest| next_complete_box(rest)) {
+        if first_box {
+            first_box = false;
+            if head.name != BoxType::FileTypeBox {
+                if !matches!(head.name, BoxType::UnknownBox(_)) {
+                    probe.format = FileFormat::OtherIsobmff;
+                }
+                return probe;
+            }
+        }
+        match head.name {
+            BoxType::FileTypeBox if probe.file_type.is_none() => {
+                let Ok(ftyp) = read_ftyp(&mut slice_box(head, content, 0)) else {
+                    probe.format = FileFormat::OtherIsobmff;
+                    return probe;
+                };
+                probe.format = file_format(&ftyp);
+                probe.is_animated = Some(SEQUENCE_BRANDS.iter().any(|&brand| ftyp.has_brand(brand)));
+                probe.file_type = Some(ftyp);
+            },
+            BoxType::MetadataBox => {
+                let mut ctx = ParseContext::new(&ParseOptions { strictness: Strictness::Lenient, ..ParseOptions::default() });
+                if let Ok(meta) = read_meta_boxes(&mut slice_box(head, content, offset.to_u64()), &mut ctx) {
+                    let primary_type = meta.item_infos.iter().find(|info| info.item_id == meta.primary_item_id).map(|info| info.item_type.value);
+                    probe.has_grid = Some(primary_type == Some(*b"grid"));
+                    probe.has_alpha = Some(has_alpha(&meta));
+                }
+                break;
+            },
+            BoxType::MediaDataBox => break,
+            _ => {},
+        }
+        offset += usize::try_from(head.size).unwrap_or(usize::MAX);
+    }
+    if first_box && data.len() >= 8 && data[4..8] == *b"ftyp" {
+        // cut off ftyp
+        probe.format = FileFormat::OtherIsobmff;
+    }
+    probe
+}
+
+/// Header and content of the box at the start of `data`, if all of it is there
+fn next_complete_box(data: &[u8]) -> Option<(BoxHeader, &[u8])> {
+    let head = read_box_header(&mut &data[..]).ok()?;
+    let content = data.get(usize::try_from(head.offset).ok()?..usize::try_from(head.size).ok()?);
+    match content {
+        Some(content) => Some((head, content)),
+        // The header of an incomplete mdat is enough to stop
+        None if head.name == BoxType::MediaDataBox => Some((head, &[])),
+        None => None,
+    }
+}
+
+fn file_format(ftyp: &FileType) -> FileFormat {
+    let classify = |brand: [u8; 4]| match &brand {
+        b"avif" => Some(FileFormat::AvifStill),
+        b"avis" => Some(FileFormat::AvifSequence),
+        brand if HEIC_BRANDS.contains(brand) => Some(FileFormat::Heic),
+        _ => None,
+    };
+    classify(ftyp.major_brand)
+        .or_else(|| [*b"avif", *b"avis"].into_iter().chain(HEIC_BRANDS).filter(|&b| ftyp.has_brand(b)).find_map(classify))
+        .unwrap_or(FileFormat::OtherIsobmff)
+}
+
+/// Like `set_alpha_item_id`, but also for HEIC alpha
+fn has_alpha(meta: &AvifInternalMeta) -> bool {
+    meta.item_references.iter()
+        .filter(|iref| iref.to_item_id == meta.primary_item_id && iref.item_type == b"auxl")
+        .any(|iref| meta.properties.iter().any(|prop| {
+            prop.item_id == iref.from_item_id
+                && matches!(&prop.property, ItemProperty::AuxiliaryType(aux) if ALPHA_URNS.contains(&aux.type_subtype().0))
+        }))
+}
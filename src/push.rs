@@ -0,0 +1,364 @@
+use crate::boxes::BoxType;
+use crate::{
+    check_ftyp, init_data, read_avif_meta, read_box_header, read_idat_primary_and_alpha, slice_box, sorted_item_extents, AvifData,
+    BoxHeader, Error, ErrorContext, ExtentRange, FileType, ItemRole, MediaDataBox, ParseContext, ParseOptions, Result, ToU64, TryVec,
+};
+
+use alloc::collections::VecDeque;
+use arrayvec::ArrayVec;
+use core::ops::Range;
+
+/// See [`AvifPushParser::next_event()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PushEvent {
+    /// Push at least this many bytes to get the next event. Pushing more at once is fine.
+    NeedMore(usize),
+    /// `ftyp` and `meta` have been parsed, and [`AvifPushParser::avif_data()`] is available
+    HeaderReady,
+    /// All data of the item has been received
+    ItemComplete(ItemRole),
+    /// All items are complete. The rest of the input is ignored.
+    Done,
+}
+
+/// Parser that is fed chunks of the file as they arrive, instead of reading from a blocking reader.
+///
+/// ```rust,no_run
+/// # fn chunks() -> Vec<Vec<u8>> { vec![] }
+/// let mut parser = avif_parse::AvifPushParser::new();
+/// for chunk in chunks() {
+///     parser.push(&chunk)?;
+///     while let Some(event) = parser.poll_event() {
+///         println!("{event:?}");
+///     }
+/// }
+/// let data = parser.into_avif_data()?;
+/// # Ok::<_, avif_parse::Error>(())
+/// ```
+///
+/// Only the primary and alpha items are read, and other items can't be selected.
+/// Only their bytes are buffered from `mdat` boxes after `meta`. `mdat` boxes before `meta` have to be buffered whole.
+pub struct AvifPushParser {
+    state: State,
+    /// File offset of the next pushed byte
+    offset: u64,
+    /// Box header being received. Large size and `uuid` make it at most 32 bytes.
+    header: ArrayVec<u8, 32>,
+    /// Kept part of the current box
+    content: TryVec<u8>,
+    seen_ftyp: bool,
+    file_type: FileType,
+    mdats: TryVec<MediaDataBox>,
+    data: Option<AvifData>,
+    has_alpha: bool,
+    primary_complete: bool,
+    alpha_complete: bool,
+    /// Extents of the primary (`true`) and alpha (`false`) items that haven't been received yet
+    pending_extents: TryVec<(bool, ExtentRange)>,
+    /// Not taken yet by `next_event` or `poll_event`
+    events: VecDeque<PushEvent>,
+    ctx: ParseContext,
+}
+
+enum State {
+    BoxHeader,
+    /// Bytes of the box in `keep` are collected into `content`, and the rest until `end` is discarded.
+    /// `head` is `None` if the box isn't needed, or after the kept part has been handled.
+    BoxContent { head: Option<BoxHeader>, keep: Range<u64>, end: u64 },
+    /// The rest of the input is only counted, to check that the box in which the items have been completed isn't truncated
+    Done { end: u64 },
+}
+
+impl Default for AvifPushParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AvifPushParser {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_context(ParseContext::default())
+    }
+
+    /// Like [`AvifPushParser::new()`], but [`AvifPushParser::push()`] fails with [`Error::LimitExceeded`] if the file exceeds the limits
+    #[must_use]
+    pub fn with_options(options: &ParseOptions) -> Self {
+        Self::with_context(ParseContext::new(options))
+    }
+
+    fn with_context(ctx: ParseContext) -> Self {
+        Self {
+            state: State::BoxHeader,
+            offset: 0,
+            header: ArrayVec::new(),
+            content: TryVec::new(),
+            seen_ftyp: false,
+            file_type: FileType::default(),
+            mdats: TryVec::new(),
+            data: None,
+            has_alpha: false,
+            primary_complete: false,
+            alpha_complete: false,
+            pending_extents: TryVec::new(),
+            events: VecDeque::new(),
+            ctx,
+        }
+    }
+
+    /// Feed the next chunk of the file. All of it is consumed.
+    ///
+    /// Errors come with the location of the box they're in. After an error the parser should not be used any more.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<(), ErrorContext> {
+        self.push_chunk(chunk).map_err(|e| self.ctx.error_context(e))
+    }
+
+    fn push_chunk(&mut self, mut chunk: &[u8]) -> Result<()> {
+        loop {
+            match &mut self.state {
+                State::Done { .. } => {
+                    self.offset += chunk.len().to_u64();
+                    return Ok(());
+                },
+                State::BoxHeader => {
+                    let needed = self.header_bytes_needed();
+                    if needed > 0 {
+                        if chunk.is_empty() {
+                            return Ok(());
+                        }
+                        let (head, rest) = chunk.split_at(needed.min(chunk.len()));
+                        self.header.try_extend_from_slice(head).map_err(|_| Error::InvalidData("box header"))?;
+                        self.offset += head.len().to_u64();
+                        chunk = rest;
+                        continue;
+                    }
+                    let head = read_box_header(&mut self.header.as_slice())?;
+                    self.header.clear();
+                    self.start_box(head)?;
+                },
+                State::BoxContent { head, keep, end } => {
+                    if self.offset < keep.start {
+                        let skip = (keep.start - self.offset).min(chunk.len().to_u64()) as usize;
+                        self.offset += skip.to_u64();
+                        chunk = &chunk[skip..];
+                    }
+                    if self.offset >= keep.start && self.offset < keep.end {
+                        let take = (keep.end - self.offset).min(chunk.len().to_u64()) as usize;
+                        self.content.extend_from_slice(&chunk[..take])?;
+                        self.offset += take.to_u64();
+                        chunk = &chunk[take..];
+                    }
+                    if self.offset >= keep.end && let Some(head) = head.take() {
+                        let keep_start = keep.start;
+                        self.box_received(head, keep_start)?;
+                        continue;
+                    }
+                    if self.offset < *end {
+                        let skip = (*end - self.offset).min(chunk.len().to_u64()) as usize;
+                        self.offset += skip.to_u64();
+                        chunk = &chunk[skip..];
+                    }
+                    if self.offset < *end {
+                        return Ok(());
+                    }
+                    self.state = State::BoxHeader;
+                },
+            }
+        }
+    }
+
+    /// Next event caused by the pushed data. Returns [`PushEvent::NeedMore`] when all events have been taken.
+    pub fn next_event(&mut self) -> PushEvent {
+        if let Some(event) = self.events.pop_front() {
+            return event;
+        }
+        let needed = match &self.state {
+            State::Done { .. } => return PushEvent::Done,
+            State::BoxHeader => self.header_bytes_needed(),
+            State::BoxContent { head: Some(_), keep, .. } => usize::try_from(keep.end - self.offset).unwrap_or(usize::MAX),
+            State::BoxContent { head: None, end, .. } => usize::try_from(*end - self.offset).unwrap_or(usize::MAX),
+        };
+        PushEvent::NeedMore(needed.max(1))
+    }
+
+    /// Like [`AvifPushParser::next_event()`], but returns `None` instead of [`PushEvent::NeedMore`],
+    /// and returns [`PushEvent::Done`] only once
+    pub fn poll_event(&mut self) -> Option<PushEvent> {
+        self.events.pop_front()
+    }
+
+    /// Available after [`PushEvent::HeaderReady`]. Items are incomplete until their [`PushEvent::ItemComplete`].
+    #[must_use]
+    pub fn avif_data(&self) -> Option<&AvifData> {
+        self.data.as_ref()
+    }
+
+    /// Returns the data after [`PushEvent::Done`], or [`Error::UnexpectedEOF`] if the input has ended too early
+    ///
+    /// In strict mode, the `mdat` box with the end of the items has to be complete too.
+    pub fn into_avif_data(self) -> Result<AvifData> {
+        match (self.state, self.data) {
+            (State::Done { end }, Some(data)) => {
+                self.ctx.check_truncated_mdat(self.offset < end)?;
+                Ok(data)
+            },
+            _ => Err(Error::UnexpectedEOF),
+        }
+    }
+
+    /// Size of the box header is known only from its first bytes
+    fn header_bytes_needed(&self) -> usize {
+        let received = self.header.as_slice();
+        let mut len = BoxHeader::MIN_SIZE as usize;
+        if received.len() >= len {
+            if received[..4] == 1u32.to_be_bytes() {
+                len = BoxHeader::MIN_LARGE_SIZE as usize;
+            }
+            if BoxType::from(u32::from_be_bytes([received[4], received[5], received[6], received[7]])) == BoxType::UuidBox {
+                len += 16;
+            }
+        }
+        len.saturating_sub(received.len())
+    }
+
+    fn start_box(&mut self, head: BoxHeader) -> Result<()> {
+        let start = self.offset;
+        let end = (start - head.offset).checked_add(head.size).ok_or(Error::InvalidData("box size overflow"))?;
+        self.ctx.check_file_size(end)?;
+        if !self.seen_ftyp && head.name != BoxType::FileTypeBox {
+            return Err(Error::InvalidData("'ftyp' box must occur first"));
+        }
+        let keep = match head.name {
+            BoxType::FileTypeBox if !self.seen_ftyp => Some(start..end),
+            BoxType::MetadataBox => {
+                if self.data.is_some() {
+                    return Err(Error::InvalidData("There should be zero or one meta boxes per ISO 14496-12:2015 § 8.11.1.1"));
+                }
+                Some(start..end)
+            },
+            // annoyingly, mdat is allowed to appear before meta
+            BoxType::MediaDataBox if self.data.is_none() => Some(start..end).filter(|r| !r.is_empty()),
+            BoxType::MediaDataBox => self.needed_span(start..end)?,
+            _ => None,
+        };
+        if let Some(keep) = &keep {
+            self.ctx.allocate(keep.end - keep.start)?;
+        }
+        self.state = match keep {
+            Some(keep) => State::BoxContent { head: Some(head), keep, end },
+            None => State::BoxContent { head: None, keep: end..end, end },
+        };
+        Ok(())
+    }
+
+    /// Part of the `mdat` at `range` covering all pending extents that start in it
+    fn needed_span(&self, range: Range<u64>) -> Result<Option<Range<u64>>> {
+        let mut span: Option<Range<u64>> = None;
+        for (_, extent) in self.pending_extents.iter().filter(|(_, e)| range.contains(&e.start())) {
+            let end = match extent {
+                ExtentRange::WithLength(r) if r.end <= range.end => r.end,
+                ExtentRange::WithLength(_) => return Err(Error::InvalidData("extent crosses box boundary")),
+                ExtentRange::ToEnd(_) => range.end,
+            };
+            span = Some(match span {
+                Some(span) => span.start.min(extent.start())..span.end.max(end),
+                None => extent.start()..end,
+            });
+        }
+        Ok(span)
+    }
+
+    fn box_received(&mut self, head: BoxHeader, offset: u64) -> Result<()> {
+        let content = core::mem::take(&mut self.content);
+        match head.name {
+            BoxType::FileTypeBox if !self.seen_ftyp => {
+                self.file_type = check_ftyp(head, &content, &mut self.ctx)?;
+                self.seen_ftyp = true;
+            },
+            BoxType::MetadataBox => {
+                let meta = read_avif_meta(slice_box(head, &content, offset - head.offset), &mut self.ctx)?;
+                meta.check_not_protected(meta.primary_and_alpha())?;
+                let mut data = init_data(&meta);
+                data.file_type = core::mem::take(&mut self.file_type);
+                let [primary_idat, alpha_idat] = read_idat_primary_and_alpha(&meta, &mut self.ctx)?;
+                data.primary_item = primary_idat;
+                if !alpha_idat.is_empty() {
+                    data.alpha_item = Some(alpha_idat);
+                }
+                data.warnings = core::mem::take(&mut self.ctx.warnings);
+                self.data = Some(data);
+                self.has_alpha = meta.alpha_item_id.is_some();
+                self.pending_extents = sorted_item_extents(&meta)?;
+                push_event(&mut self.events, PushEvent::HeaderReady)?;
+                for mdat in core::mem::take(&mut self.mdats) {
+                    self.read_pending_extents(&mdat)?;
+                }
+                self.check_complete()?;
+            },
+            BoxType::MediaDataBox if self.data.is_none() => {
+                self.mdats.push(MediaDataBox { offset, data: content })?;
+            },
+            BoxType::MediaDataBox => {
+                self.read_pending_extents(&MediaDataBox { offset, data: content })?;
+                self.check_complete()?;
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
+    fn read_pending_extents(&mut self, mdat: &MediaDataBox) -> Result<()> {
+        let Some(data) = &mut self.data else {
+            return Ok(());
+        };
+        let mut still_pending = TryVec::new();
+        for (is_primary, extent) in core::mem::take(&mut self.pending_extents) {
+            if !mdat.contains_extent(&extent) {
+                still_pending.push((is_primary, extent))?;
+                continue;
+            }
+            let item_data = if is_primary {
+                &mut data.primary_item
+            } else {
+                data.alpha_item.get_or_insert_with(TryVec::new)
+            };
+            mdat.read_extent(&extent, item_data)?;
+        }
+        self.pending_extents = still_pending;
+        Ok(())
+    }
+
+    fn check_complete(&mut self) -> Result<()> {
+        let Some(data) = &mut self.data else {
+            return Ok(());
+        };
+        if !self.primary_complete && !self.pending_extents.iter().any(|&(is_primary, _)| is_primary) {
+            self.ctx.check_av1_dimensions(&data.primary_item, None)?;
+            data.fill_hdr_from_obus();
+            self.primary_complete = true;
+            push_event(&mut self.events, PushEvent::ItemComplete(ItemRole::Primary))?;
+        }
+        if self.has_alpha && !self.alpha_complete && !self.pending_extents.iter().any(|&(is_primary, _)| !is_primary) {
+            self.ctx.check_av1_dimensions(data.alpha_item.as_deref().unwrap_or_default(), None)?;
+            self.alpha_complete = true;
+            push_event(&mut self.events, PushEvent::ItemComplete(ItemRole::Alpha))?;
+        }
+        if self.pending_extents.is_empty() {
+            let end = match self.state {
+                State::BoxContent { end, .. } => end,
+                _ => self.offset,
+            };
+            push_event(&mut self.events, PushEvent::Done)?;
+            self.state = State::Done { end };
+        }
+        Ok(())
+    }
+}
+
+/// Like `push_back`, but doesn't abort if the allocation fails
+fn push_event(events: &mut VecDeque<PushEvent>, event: PushEvent) -> Result<()> {
+    events.try_reserve(1)?;
+    events.push_back(event);
+    Ok(())
+}
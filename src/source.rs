@@ -0,0 +1,145 @@
+use crate::{Error, Result, ToU64, TryVec};
+
+/// Minimal replacement for `std::io::Read`, used when the `std` feature is disabled.
+///
+/// It's implemented for `&[u8]`. With the `std` feature, the public API takes `std::io::Read` instead.
+pub trait ByteSource {
+    /// Reads up to `buf.len()` bytes. Returns 0 only at the end of the data.
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize>;
+}
+
+impl ByteSource for &[u8] {
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = buf.len().min(self.len());
+        let (head, rest) = self.split_at(len);
+        buf[..len].copy_from_slice(head);
+        *self = rest;
+        Ok(len)
+    }
+}
+
+impl<S: ByteSource + ?Sized> ByteSource for &mut S {
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize> {
+        (**self).read_bytes(buf)
+    }
+}
+
+/// Readers taken by the public API
+#[cfg(feature = "std")]
+pub(crate) use std::io::Read as Input;
+#[cfg(not(feature = "std"))]
+pub(crate) use ByteSource as Input;
+
+/// Reads from an [`Input`] without knowing which trait it is
+#[cfg(feature = "std")]
+pub(crate) fn read_input<R: Input + ?Sized>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    loop {
+        match reader.read(buf) {
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {},
+            res => return Ok(res?),
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn read_input<R: Input + ?Sized>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    reader.read_bytes(buf)
+}
+
+/// Helpers that `std::io::Read` and `byteorder` used to provide
+pub(crate) trait ByteSourceExt: ByteSource {
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.read_bytes(buf)? {
+                0 => return Err(Error::UnexpectedEOF),
+                n => buf = &mut buf[n..],
+            }
+        }
+        Ok(())
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        let mut buf = [0; 1];
+        self.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    /// Like `read_to_end`, but the space for `limit` bytes is reserved up front
+    fn read_to_try_vec(&mut self, limit: u64) -> Result<TryVec<u8>> {
+        let mut vec = alloc::vec::Vec::new();
+        vec.try_reserve_exact(usize::try_from(limit).map_err(|_| Error::OutOfMemory)?)?;
+        // The box size may be bogus, so the memory is zeroed in growing steps only as the data arrives
+        let mut filled = 0;
+        while filled < vec.capacity() {
+            if filled == vec.len() {
+                let step = filled.max(4096).min(vec.capacity() - filled);
+                vec.resize(filled + step, 0);
+            }
+            match self.read_bytes(&mut vec[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        vec.truncate(filled);
+        Ok(vec.into())
+    }
+
+    /// Reads and discards up to `len` bytes, returning how many there were
+    fn skip_bytes(&mut self, mut len: u64) -> Result<u64> {
+        let mut buf = [0; 4096];
+        let mut skipped = 0;
+        while len > 0 {
+            let chunk = len.min(buf.len().to_u64()) as usize;
+            match self.read_bytes(&mut buf[..chunk])? {
+                0 => break,
+                n => {
+                    skipped += n.to_u64();
+                    len -= n.to_u64();
+                },
+            }
+        }
+        Ok(skipped)
+    }
+}
+
+impl<S: ByteSource + ?Sized> ByteSourceExt for S {}
+
+/// Like `std::io::Take`, limits reading to the content of a box
+pub(crate) struct Take<T> {
+    inner: T,
+    limit: u64,
+}
+
+impl<T> Take<T> {
+    pub(crate) fn new(inner: T, limit: u64) -> Self {
+        Self { inner, limit }
+    }
+
+    pub(crate) fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    pub(crate) fn set_limit(&mut self, limit: u64) {
+        self.limit = limit;
+    }
+
+    pub(crate) fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T: ByteSource> ByteSource for Take<T> {
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let max = buf.len().min(usize::try_from(self.limit).unwrap_or(usize::MAX));
+        if max == 0 {
+            return Ok(0);
+        }
+        let n = self.inner.read_bytes(&mut buf[..max])?;
+        self.limit -= n.to_u64();
+        Ok(n)
+    }
+}
@@ -1,13 +1,16 @@
 // This Source Code Form is subject to the terms of the Mozilla Public
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
-use avif_parse::{ContentLightLevel, Error, MasteringDisplayColourVolume};
+use avif_parse::{AV1Metadata, AlphaGeometryIssue, AvifBrand, AvifProfile, AvifPushParser, BoxFields, BoxNode, FileFormat, ContentLightLevel, Error, ErrorContext, ImageSpatialExtents, ItemExtent, ItemReference, ItemRole, MasteringDisplayColourVolume, ObuIter, ObuMetadata, ObuType, ParseOptions, ProfileViolation, Property, PushEvent, StillImageViolation, Strictness, UnsupportedFeature, WarningCode};
+use std::borrow::Cow;
 use std::fs::File;
 
 static IMAGE_AVIF: &str = "av1-avif/testFiles/Microsoft/Monochrome.avif";
 static IMAGE_AVIF_EXTENTS: &str = "tests/kodim-extents.avif";
 static IMAGE_AVIF_CORRUPT: &str = "tests/bug-1655846.avif";
 static IMAGE_AVIF_CORRUPT_2: &str = "tests/bug-1661347.avif";
+/// `IMAGE_AVIF_EXTENTS` with an Exif item split into two extents of `idat`
+static IMAGE_AVIF_IDAT: &str = "tests/kodim-idat.avif";
 static AOMEDIA_TEST_FILES: &str = "av1-avif/testFiles";
 static LINK_U_SAMPLES: &str = "link-u-samples";
 
@@ -123,3 +126,965 @@ fn parse_clli_and_mdcv() {
         min_luminance: 50,
     });
 }
+
+/// Temporal delimiter and sequence header from a fixture, followed by metadata OBUs
+fn av1_with_metadata_obus() -> Vec<u8> {
+    let input = &mut File::open(IMAGE_AVIF_EXTENTS).expect("Unknown file");
+    let mut av1 = avif_parse::read_avif(input).expect("read_avif failed").primary_item[..13].to_vec();
+    // METADATA_TYPE_HDR_CLL
+    av1.extend([0x2A, 6, 1, 0x03, 0xE8, 0x01, 0x90, 0x80]);
+    // METADATA_TYPE_HDR_MDCV, BT.2020 R, G, B and D65, 1000 and 0.005 cd/m²
+    av1.extend([0x2A, 26, 2]);
+    for v in [46399u16, 19137, 11141, 52232, 8585, 3015, 20493, 21561] {
+        av1.extend(v.to_be_bytes());
+    }
+    av1.extend((1000u32 << 8).to_be_bytes());
+    av1.extend(82u32.to_be_bytes());
+    av1.push(0x80);
+    // METADATA_TYPE_ITUT_T35 with HDR10+ provider code
+    av1.extend([0x2A, 9, 4, 0xB5, 0x00, 0x3C, 0x00, 0x01, 0x04, 0x80, 0x00]);
+    av1
+}
+
+#[test]
+fn av1_metadata_obus() {
+    let av1 = av1_with_metadata_obus();
+    let metadata = AV1Metadata::parse_metadata_obus(&av1).unwrap();
+    assert_eq!(metadata.len(), 3);
+    assert_eq!(metadata[0], ObuMetadata::ContentLightLevel(ContentLightLevel {
+        max_content_light_level: 1000,
+        max_pic_average_light_level: 400,
+    }));
+    let ObuMetadata::MasteringDisplay(mdcv) = &metadata[1] else { panic!("{:?}", metadata[1]) };
+    assert_eq!(mdcv.primaries, [(8500, 39850), (6550, 2300), (35400, 14600)]);
+    assert_eq!(mdcv.white_point, (15635, 16450));
+    assert_eq!(mdcv.max_luminance, 10_000_000);
+    assert_eq!(mdcv.min_luminance, 50);
+    let ObuMetadata::ItuTT35(t35) = &metadata[2] else { panic!("{:?}", metadata[2]) };
+    assert_eq!(t35.country_code, 0xB5);
+    assert_eq!(t35.country_code_extension, None);
+    assert_eq!(t35.payload, [0x00, 0x3C, 0x00, 0x01, 0x04]);
+
+    let meta = AV1Metadata::parse_av1_bitstream(&av1).unwrap();
+    assert_eq!(meta.content_light_level.unwrap().max_content_light_level, 1000);
+    assert_eq!(meta.mastering_display, Some(*mdcv));
+}
+
+#[test]
+fn hdr_metadata_from_obus() {
+    // `IMAGE_AVIF_EXTENTS` with the metadata OBUs appended to the primary item
+    let mut file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    let obus = &av1_with_metadata_obus()[13..];
+    file.extend_from_slice(obus);
+    let iloc = file.windows(4).position(|w| w == b"iloc").unwrap() + 4;
+    for size_offset in [iloc + 26, 188] {
+        let size = u32::from_be_bytes(file[size_offset..size_offset + 4].try_into().unwrap()) + obus.len() as u32;
+        file[size_offset..size_offset + 4].copy_from_slice(&size.to_be_bytes());
+    }
+
+    let header = avif_parse::AvifHeader::from_reader(file.as_slice()).unwrap();
+    assert!(header.content_light_level().is_none());
+    let data = header.read_avif().unwrap();
+    assert_eq!(data.content_light_level().unwrap().max_content_light_level, 1000);
+    assert_eq!(data.mastering_display().unwrap().max_luminance, 10_000_000);
+    let borrowed = avif_parse::read_avif_slice(&file).unwrap();
+    assert_eq!(borrowed.content_light_level, data.content_light_level);
+    assert_eq!(borrowed.mastering_display, data.mastering_display);
+    let mut parser = AvifPushParser::new();
+    parser.push(&file).unwrap();
+    assert_eq!(parser.into_avif_data().unwrap().content_light_level, data.content_light_level);
+
+    // The container's properties take precedence
+    let hdr = avif_parse::read_avif(&mut File::open("tests/hdr-clli.avif").unwrap()).unwrap();
+    assert_eq!(hdr.content_light_level().unwrap().max_content_light_level, 1000);
+    assert!(hdr.mastering_display().is_none());
+}
+
+#[test]
+fn av1_metadata_scalability() {
+    // SCALABILITY_SS with 2 layers, dimensions and a temporal group with 1 entry
+    let obu = [0x2A, 15, 3, 14, 0b0110_1000, 0, 63, 0, 63, 0, 127, 0, 127, 1, 0b0011_0001, 1, 0x80];
+    let metadata = AV1Metadata::parse_metadata_obus(&obu).unwrap();
+    let ObuMetadata::Scalability(s) = &metadata[0] else { panic!("{:?}", metadata[0]) };
+    assert_eq!(s.scalability_mode_idc, 14);
+    let structure = s.structure.as_ref().unwrap();
+    assert_eq!(structure.spatial_layers.len(), 2);
+    assert_eq!(structure.spatial_layers[0].dimensions, Some((64, 64)));
+    assert_eq!(structure.spatial_layers[1].dimensions, Some((128, 128)));
+    assert_eq!(structure.spatial_layers[1].ref_id, None);
+    let group = structure.temporal_group().unwrap().unwrap();
+    assert_eq!(group.len(), 1);
+    assert_eq!(group[0].temporal_id, 1);
+    assert!(group[0].temporal_switching_up_point);
+    assert_eq!(group[0].ref_pic_diff.as_slice(), [1]);
+}
+
+#[test]
+fn obu_iter() {
+    let input = &mut File::open(IMAGE_AVIF_EXTENTS).expect("Unknown file");
+    let context = avif_parse::read_avif(input).expect("read_avif failed");
+    let obus = ObuIter::new(&context.primary_item).collect::<Result<Vec<_>, _>>().unwrap();
+    let types: Vec<_> = obus.iter().map(|o| o.obu_type).collect();
+    assert_eq!(types, [ObuType::TemporalDelimiter, ObuType::SequenceHeader, ObuType::FrameHeader, ObuType::TileGroup]);
+    assert!(obus.iter().all(|o| o.extension.is_none() && o.has_size_field));
+    assert_eq!(obus[1].payload.len(), 9);
+    assert_eq!(obus.iter().map(|o| o.data.len()).sum::<usize>(), context.primary_item.len());
+
+    let av1 = av1_with_metadata_obus();
+    let stripped: Vec<u8> = ObuIter::new(&av1)
+        .map(Result::unwrap)
+        .filter(|o| o.obu_type != ObuType::Metadata)
+        .flat_map(|o| o.data.iter().copied())
+        .collect();
+    assert_eq!(stripped, context.primary_item[..13]);
+
+    let mut truncated = ObuIter::new(&context.primary_item[..10]);
+    assert!(truncated.nth(1).unwrap().is_err());
+    assert!(truncated.next().is_none());
+}
+
+#[test]
+fn annexb_round_trip() {
+    let input = &mut File::open(IMAGE_AVIF_EXTENTS).expect("Unknown file");
+    let context = avif_parse::read_avif(input).expect("read_avif failed");
+    let low_overhead = &context.primary_item[..];
+    assert!(!avif_parse::is_annexb(low_overhead));
+
+    let annexb = avif_parse::low_overhead_to_annexb(low_overhead).unwrap();
+    assert!(avif_parse::is_annexb(&annexb));
+    let types: Vec<_> = ObuIter::new_annexb(&annexb).map(|o| o.unwrap().obu_type).collect();
+    assert_eq!(types, [ObuType::TemporalDelimiter, ObuType::SequenceHeader, ObuType::FrameHeader, ObuType::TileGroup]);
+    assert!(ObuIter::detect(&annexb).all(|o| !o.unwrap().has_size_field));
+
+    let meta = AV1Metadata::parse_av1_bitstream(&annexb).unwrap();
+    assert_eq!(meta.max_frame_width.get(), 120);
+    assert_eq!(meta.max_frame_height.get(), 80);
+
+    let back = avif_parse::annexb_to_low_overhead(&annexb).unwrap();
+    assert_eq!(back, low_overhead);
+}
+
+#[test]
+fn validate_still_image() {
+    let input = &mut File::open(IMAGE_AVIF_EXTENTS).expect("Unknown file");
+    let mut context = avif_parse::read_avif(input).expect("read_avif failed");
+    assert!(context.validate_still_image().unwrap().is_empty());
+
+    let single = context.primary_item.to_vec();
+    let mut doubled = single.clone();
+    doubled.extend_from_slice(&single);
+    // A different sequence header in the second temporal unit
+    let mut mismatched = doubled.clone();
+    mismatched[single.len() + 5] ^= 1;
+
+    context.primary_item = doubled.into();
+    context.alpha_item = Some(single[..2].to_vec().into());
+    let diagnostics = context.validate_still_image().unwrap();
+    let violations: Vec<_> = diagnostics.iter().map(|d| (d.item, d.violation)).collect();
+    assert_eq!(violations, [
+        (ItemRole::Primary, StillImageViolation::MultipleTemporalUnits { count: 2 }),
+        (ItemRole::Primary, StillImageViolation::MultipleFrames { count: 2 }),
+        (ItemRole::Alpha, StillImageViolation::MissingSequenceHeader),
+        (ItemRole::Alpha, StillImageViolation::MissingFrame),
+    ]);
+    assert!(diagnostics.iter().all(|d| !d.violation.is_recommendation()));
+
+    context.primary_item = mismatched.into();
+    context.alpha_item = None;
+    let diagnostics = context.validate_still_image().unwrap();
+    assert_eq!(diagnostics[0].violation, StillImageViolation::MismatchedSequenceHeaders);
+}
+
+#[test]
+fn alpha_geometry() {
+    let input = &mut File::open(IMAGE_AVIF_EXTENTS).expect("Unknown file");
+    let mut context = avif_parse::read_avif(input).expect("read_avif failed");
+    assert_eq!(context.primary_spatial_extents, Some(ImageSpatialExtents { width: 120, height: 80 }));
+    assert!(context.check_alpha_geometry().unwrap().is_empty());
+
+    // The same 4:4:4 image used as alpha
+    context.alpha_item = Some(context.primary_item.to_vec().into());
+    context.alpha_spatial_extents = context.primary_spatial_extents;
+    let issues = context.check_alpha_geometry().unwrap();
+    assert_eq!(issues[..], [AlphaGeometryIssue::NotMonochrome]);
+    assert!(!issues[0].is_error());
+
+    let mut alpha = context.primary_item.to_vec();
+    alpha[6] ^= 0x08; // max_frame_width_minus_1
+    context.alpha_item = Some(alpha.into());
+    context.alpha_spatial_extents = Some(ImageSpatialExtents { width: 64, height: 80 });
+    let issues = context.check_alpha_geometry().unwrap();
+    assert_eq!(issues[..2], [
+        AlphaGeometryIssue::Dimensions { color: (120, 80), alpha: (104, 80) },
+        AlphaGeometryIssue::SpatialExtents {
+            color: ImageSpatialExtents { width: 120, height: 80 },
+            alpha: ImageSpatialExtents { width: 64, height: 80 },
+        },
+    ]);
+    assert!(issues[0].is_error());
+    assert!(!AlphaGeometryIssue::BitDepth { color: 10, alpha: 8 }.is_error());
+}
+
+#[test]
+fn read_avif_slice() {
+    for path in [IMAGE_AVIF_EXTENTS, IMAGE_AVIF_CORRUPT, IMAGE_AVIF_CORRUPT_2, "tests/hdr-clli.avif", "tests/hdr-mdcv.avif", "tests/hdr-clli-mdcv.avif", "idat primary"] {
+        let file = if path == "idat primary" { idat_primary() } else { std::fs::read(path).unwrap() };
+        let copied = avif_parse::read_avif(&mut file.as_slice());
+        let borrowed = avif_parse::read_avif_slice(&file);
+        let (Ok(copied), Ok(borrowed)) = (&copied, &borrowed) else {
+            assert!(copied.is_err() && borrowed.is_err(), "{path}");
+            continue;
+        };
+        assert_eq!(copied.primary_item[..], borrowed.primary_item[..]);
+        assert_eq!(copied.alpha_item.as_deref(), borrowed.alpha_item.as_deref());
+        assert_eq!(copied.content_light_level, borrowed.content_light_level);
+        assert_eq!(copied.mastering_display, borrowed.mastering_display);
+        // Only split and idat items need copying
+        assert_eq!(matches!(borrowed.primary_item, Cow::Owned(_)), path == IMAGE_AVIF_EXTENTS || path == "idat primary", "{path}");
+    }
+}
+
+/// `IMAGE_AVIF_IDAT` with its `idat` item turned into the primary item
+fn idat_primary() -> Vec<u8> {
+    let mut file = std::fs::read(IMAGE_AVIF_IDAT).unwrap();
+    assert_eq!(&file[36..40], b"pitm");
+    file[45] = 2;
+    assert_eq!(&file[149..165], b"infe\x02\0\0\x01\0\x02\0\0Exif");
+    file[156] = 0; // not hidden
+    file[161..165].copy_from_slice(b"av01");
+    file
+}
+
+/// `IMAGE_AVIF_EXTENTS` with mdat moved before meta
+fn mdat_before_meta() -> Vec<u8> {
+    let file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    let (ftyp, rest) = file.split_at(20);
+    let (meta, mdat) = rest.split_at(168);
+    let mut moved = [ftyp, mdat, meta].concat();
+    let iloc = moved.windows(4).position(|w| w == b"iloc").unwrap() + 4;
+    for extent_offset in [iloc + 14, iloc + 22] {
+        let offset = u32::from_be_bytes(moved[extent_offset..extent_offset + 4].try_into().unwrap()) - 168;
+        moved[extent_offset..extent_offset + 4].copy_from_slice(&offset.to_be_bytes());
+    }
+    moved
+}
+
+#[test]
+fn read_avif_seekable() {
+    for path in [IMAGE_AVIF_EXTENTS, IMAGE_AVIF_CORRUPT, IMAGE_AVIF_CORRUPT_2, "tests/hdr-clli.avif", "tests/hdr-mdcv.avif", "tests/hdr-clli-mdcv.avif", "idat primary"] {
+        let file = if path == "idat primary" { idat_primary() } else { std::fs::read(path).unwrap() };
+        let read = avif_parse::read_avif(&mut file.as_slice());
+        let seeked = avif_parse::read_avif_seekable(&mut std::io::Cursor::new(&file));
+        let (Ok(read), Ok(seeked)) = (&read, &seeked) else {
+            assert!(read.is_err() && seeked.is_err(), "{path}");
+            continue;
+        };
+        assert_eq!(read.primary_item[..], seeked.primary_item[..]);
+        assert_eq!(read.alpha_item.as_deref(), seeked.alpha_item.as_deref());
+        assert_eq!(read.premultiplied_alpha, seeked.premultiplied_alpha);
+    }
+    // mdat is skipped while reading the header
+    let moved = mdat_before_meta();
+    let expected = avif_parse::read_avif(&mut moved.as_slice()).unwrap();
+    let header = avif_parse::AvifHeader::from_seekable_reader(std::io::Cursor::new(&moved)).unwrap();
+    assert_eq!(header.read_avif_seek().unwrap().primary_item[..], expected.primary_item[..]);
+    let header = avif_parse::AvifHeader::from_seekable_reader(std::io::Cursor::new(&moved)).unwrap();
+    assert!(matches!(header.read_avif(), Err(Error::Unsupported(_))));
+}
+
+#[test]
+fn item_byte_ranges() {
+    // Stand-in for a server supporting range requests
+    let file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    let fetch = |start: u64, end: Option<u64>| {
+        let end = end.map_or(file.len(), |end| end as usize);
+        (start, &file[start as usize..end])
+    };
+
+    let expected = avif_parse::read_avif(&mut file.as_slice()).unwrap();
+    // ftyp + meta
+    let header = avif_parse::AvifHeader::from_reader(&file[..188]).unwrap();
+    let ranges = header.item_byte_ranges().unwrap();
+    assert_eq!(ranges.len(), 2);
+    assert!(ranges.iter().all(|r| r.item == ItemRole::Primary && r.item_id == 1 && &r.item_type == b"av01" && r.end.is_some()));
+    assert!(ranges[0].start < ranges[1].start);
+
+    let fetched = ranges.iter().map(|r| fetch(r.start, r.end)).collect::<Vec<_>>();
+    let item = header.read_item_from_ranges(1, &fetched).unwrap();
+    assert_eq!(item[..], expected.primary_item[..]);
+    let data = header.read_avif_from_ranges(&fetched).unwrap();
+    assert_eq!(data.primary_item[..], expected.primary_item[..]);
+    assert_eq!(data.alpha_item.as_deref(), None);
+
+    // A single range covering both extents
+    let header = avif_parse::AvifHeader::from_reader(&file[..188]).unwrap();
+    let data = header.read_avif_from_ranges(&[fetch(ranges[0].start, None)]).unwrap();
+    assert_eq!(data.primary_item[..], expected.primary_item[..]);
+
+    let header = avif_parse::AvifHeader::from_reader(&file[..188]).unwrap();
+    assert!(matches!(header.read_avif_from_ranges(&fetched[..1]), Err(Error::UnexpectedEOF)));
+
+    // An extent split between two fetched ranges
+    let middle = ranges[1].start + 100;
+    let split = [fetch(ranges[0].start, Some(middle)), fetch(middle, None)];
+    let header = avif_parse::AvifHeader::from_reader(&file[..188]).unwrap();
+    assert_eq!(header.read_avif_from_ranges(&split).unwrap().primary_item[..], expected.primary_item[..]);
+
+    // An extent to the end of a skipped mdat ends there, and not at the end of the fetched data
+    let mut moved = mdat_before_meta();
+    let iloc = moved.windows(4).position(|w| w == b"iloc").unwrap() + 4;
+    moved[iloc + 26..iloc + 30].fill(0);
+    let header = avif_parse::AvifHeader::from_seekable_reader(std::io::Cursor::new(&moved)).unwrap();
+    let ranges = header.item_byte_ranges().unwrap();
+    assert_eq!(ranges[1].end, Some(20 + 4395));
+    let data = header.read_avif_from_ranges(&[(ranges[0].start, &moved[ranges[0].start as usize..])]).unwrap();
+    assert_eq!(data.primary_item[..], expected.primary_item[..]);
+
+    // Only the selected items are planned and read
+    let file = std::fs::read(IMAGE_AVIF_CORRUPT_2).unwrap();
+    let meta_end = 32 + 438;
+    let header = avif_parse::AvifHeader::from_reader(&file[..meta_end]).unwrap();
+    assert!(header.item_byte_ranges().unwrap().iter().any(|r| r.item == ItemRole::Alpha));
+    let header = avif_parse::AvifHeader::from_reader(&file[..meta_end]).unwrap()
+        .load_primary(false)
+        .load_alpha(false)
+        .load_metadata(true);
+    let ranges = header.item_byte_ranges().unwrap();
+    assert!(ranges.iter().all(|r| r.item == ItemRole::Metadata && r.item_id == 3));
+    let fetched = ranges.iter().map(|r| (r.start, &file[r.start as usize..r.end.unwrap() as usize])).collect::<Vec<_>>();
+    let data = header.read_avif_from_ranges(&fetched).unwrap();
+    assert!(data.primary_item.is_empty());
+    assert!(data.alpha_item.is_none());
+    assert_eq!(data.other_items.len(), 1);
+    assert_eq!(&data.other_items[0].1[..], &file[5050..5266]);
+}
+
+#[test]
+fn push_parser() {
+    for path in [IMAGE_AVIF_EXTENTS, IMAGE_AVIF_CORRUPT, IMAGE_AVIF_CORRUPT_2, "tests/hdr-clli.avif", "tests/hdr-mdcv.avif", "mdat before meta", "idat primary"] {
+        let file = match path {
+            "mdat before meta" => mdat_before_meta(),
+            "idat primary" => idat_primary(),
+            _ => std::fs::read(path).unwrap(),
+        };
+        let expected = avif_parse::read_avif(&mut file.as_slice());
+        for chunk_size in [1, 7, 100, file.len()] {
+            let mut parser = AvifPushParser::new();
+            let mut events = Vec::new();
+            let mut result = Ok(());
+            for chunk in file.chunks(chunk_size) {
+                result = parser.push(chunk);
+                if result.is_err() {
+                    break;
+                }
+                while let Some(event) = parser.poll_event() {
+                    events.push(event);
+                }
+            }
+            let Ok(expected) = &expected else {
+                assert!(result.is_err() || parser.into_avif_data().is_err(), "{path}");
+                continue;
+            };
+            result.unwrap();
+            assert_eq!(events[0], PushEvent::HeaderReady);
+            assert_eq!(events[1], PushEvent::ItemComplete(ItemRole::Primary));
+            assert_eq!(events.last(), Some(&PushEvent::Done));
+            assert_eq!(parser.next_event(), PushEvent::Done);
+            let data = parser.into_avif_data().unwrap();
+            assert_eq!(data.primary_item[..], expected.primary_item[..]);
+            assert_eq!(data.alpha_item.as_deref(), expected.alpha_item.as_deref());
+            assert_eq!(data.mastering_display, expected.mastering_display);
+        }
+    }
+
+    let file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    let mut parser = AvifPushParser::new();
+    assert_eq!(parser.next_event(), PushEvent::NeedMore(8));
+    parser.push(&file[..100]).unwrap();
+    assert_eq!(parser.next_event(), PushEvent::NeedMore(88));
+    parser.push(&file[100..188]).unwrap();
+    assert_eq!(parser.next_event(), PushEvent::HeaderReady);
+    assert!(parser.avif_data().is_some());
+    assert!(matches!(parser.next_event(), PushEvent::NeedMore(_)));
+    assert!(matches!(parser.into_avif_data(), Err(Error::UnexpectedEOF)));
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn read_avif_async() {
+    // Reading from a slice never has to wait
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+        match std::pin::pin!(fut).poll(&mut cx) {
+            std::task::Poll::Ready(res) => res,
+            std::task::Poll::Pending => unreachable!(),
+        }
+    }
+
+    for path in [IMAGE_AVIF_EXTENTS, IMAGE_AVIF_CORRUPT, IMAGE_AVIF_CORRUPT_2, "tests/hdr-clli.avif", "tests/hdr-mdcv.avif", "mdat before meta", "idat primary"] {
+        let file = match path {
+            "mdat before meta" => mdat_before_meta(),
+            "idat primary" => idat_primary(),
+            _ => std::fs::read(path).unwrap(),
+        };
+        let expected = avif_parse::read_avif(&mut file.as_slice());
+        let data = block_on(avif_parse::read_avif_async(&mut file.as_slice()));
+        let (Ok(expected), Ok(data)) = (&expected, &data) else {
+            assert!(expected.is_err() && data.is_err(), "{path}");
+            continue;
+        };
+        assert_eq!(data.primary_item[..], expected.primary_item[..]);
+        assert_eq!(data.alpha_item.as_deref(), expected.alpha_item.as_deref());
+        assert_eq!(data.content_light_level, expected.content_light_level);
+    }
+
+    let file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    let mut options = ParseOptions::default();
+    options.max_dimensions = Some((119, 80));
+    let data = block_on(avif_parse::read_avif_async_with_options(&mut file.as_slice(), &options));
+    assert!(matches!(data, Err(ErrorContext { error: Error::LimitExceeded("max_dimensions"), .. })));
+    options.max_dimensions = None;
+    options.max_alloc_size = Some(1000);
+    let data = block_on(avif_parse::read_avif_async_with_options(&mut file.as_slice(), &options));
+    assert!(matches!(data, Err(ErrorContext { error: Error::LimitExceeded("max_alloc_size"), .. })));
+
+    let mut pitm_version = file.clone();
+    pitm_version[40] = 2;
+    let context = block_on(avif_parse::read_avif_async_with_options(&mut pitm_version.as_slice(), &ParseOptions::default())).unwrap_err();
+    assert_eq!(context.box_path.as_slice(), [*b"meta", *b"pitm"]);
+    assert_eq!(context.offset, 32);
+
+    let file = std::fs::read(IMAGE_AVIF_IDAT).unwrap();
+    let header = block_on(avif_parse::AvifHeader::from_async_reader(file.as_slice())).unwrap();
+    let data = block_on(header.load_metadata(true).read_avif_async()).unwrap();
+    assert_eq!(data.other_items.len(), 1);
+    assert_eq!(&data.other_items[0].1[..], b"\0\0\0\0MM\0*\0\0\0\x08\0\0");
+
+    let mut mdat_size = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    mdat_size[191] += 1;
+    let mut options = ParseOptions::default();
+    options.strictness = Strictness::Strict;
+    let data = block_on(avif_parse::read_avif_async_with_options(&mut mdat_size.as_slice(), &options));
+    assert!(matches!(data, Err(ErrorContext { error: Error::InvalidData("mdat is truncated"), .. })));
+    block_on(avif_parse::read_avif_async(&mut mdat_size.as_slice())).unwrap();
+}
+
+#[test]
+fn parse_options_limits() {
+    let file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    let read = |options: &ParseOptions| avif_parse::read_avif_with_options(&mut file.as_slice(), options);
+
+    let mut options = ParseOptions::default();
+    let expected = read(&options).unwrap();
+    options.max_file_size = Some(file.len() as u64);
+    options.max_item_count = Some(1);
+    options.max_extents_per_item = Some(2);
+    options.max_dimensions = Some((120, 80));
+    options.max_alloc_size = Some(2 * file.len() as u64);
+    assert_eq!(read(&options).unwrap().primary_item[..], expected.primary_item[..]);
+
+    let exceeded = |change: fn(&mut ParseOptions)| {
+        let mut options = options;
+        change(&mut options);
+        read(&options).is_err_and(|e| matches!(e.error, Error::LimitExceeded(_)))
+    };
+    assert!(exceeded(|o| o.max_file_size = Some(4582)));
+    assert!(exceeded(|o| o.max_item_count = Some(0)));
+    assert!(exceeded(|o| o.max_extents_per_item = Some(1)));
+    assert!(exceeded(|o| o.max_dimensions = Some((119, 80))));
+    assert!(exceeded(|o| o.max_alloc_size = Some(1000)));
+
+    options.max_alloc_size = Some(1000);
+    let header = avif_parse::AvifHeader::from_reader_with_options(file.as_slice(), &options).unwrap();
+    assert!(matches!(header.read_avif(), Err(Error::LimitExceeded("max_alloc_size"))));
+
+    // The limits apply to all the readers
+    options.max_alloc_size = None;
+    options.max_file_size = Some(4582);
+    assert!(matches!(avif_parse::read_avif_slice_with_options(&file, &options), Err(ErrorContext { error: Error::LimitExceeded("max_file_size"), .. })));
+    assert!(matches!(avif_parse::read_avif_seekable_with_options(&mut std::io::Cursor::new(&file), &options), Err(ErrorContext { error: Error::LimitExceeded("max_file_size"), .. })));
+    let mut parser = AvifPushParser::with_options(&options);
+    assert!(parser.push(&file).is_err_and(|e| matches!(e.error, Error::LimitExceeded("max_file_size"))));
+    options.max_file_size = None;
+    options.max_dimensions = Some((119, 80));
+    assert!(matches!(avif_parse::read_avif_slice_with_options(&file, &options), Err(ErrorContext { error: Error::LimitExceeded("max_dimensions"), .. })));
+    assert!(matches!(avif_parse::read_avif_seekable_with_options(&mut std::io::Cursor::new(&file), &options), Err(ErrorContext { error: Error::LimitExceeded("max_dimensions"), .. })));
+    let mut parser = AvifPushParser::with_options(&options);
+    assert!(parser.push(&file).is_err_and(|e| matches!(e.error, Error::LimitExceeded("max_dimensions"))));
+    // Without ispe getting in the way, the sequence header is checked after fetching the ranges
+    let mut small_ispe = file.clone();
+    small_ispe[147..155].copy_from_slice(&[0, 0, 0, 1, 0, 0, 0, 1]);
+    let header = avif_parse::AvifHeader::from_reader_with_options(&small_ispe[..188], &options).unwrap();
+    let fetched = [(188, &file[188..])];
+    assert!(matches!(header.read_avif_from_ranges(&fetched), Err(Error::LimitExceeded("max_dimensions"))));
+    options.max_dimensions = None;
+    options.max_alloc_size = Some(1000);
+    let header = avif_parse::AvifHeader::from_reader_with_options(&file[..188], &options).unwrap();
+    assert!(matches!(header.read_item_from_ranges(1, &fetched), Err(Error::LimitExceeded("max_alloc_size"))));
+    assert!(matches!(header.read_avif_from_ranges(&fetched), Err(Error::LimitExceeded("max_alloc_size"))));
+
+    // The sequence header in av1C is checked before the items are read, even if ispe is smaller
+    let mut file = std::fs::read(IMAGE_AVIF_CORRUPT_2).unwrap();
+    file[254..262].copy_from_slice(&[0, 0, 0, 1, 0, 0, 0, 1]);
+    let mut options = ParseOptions::default();
+    options.max_dimensions = Some((100, 100));
+    let header = avif_parse::AvifHeader::from_reader_with_options(&file[..534], &options);
+    assert!(matches!(header, Err(ErrorContext { error: Error::LimitExceeded("max_dimensions"), .. })));
+}
+
+#[test]
+fn strictness() {
+    let read = |file: &[u8], strictness| {
+        let mut options = ParseOptions::default();
+        options.strictness = strictness;
+        avif_parse::read_avif_with_options(&mut &file[..], &options)
+    };
+    let file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    let find = |name: &[u8]| file.windows(4).position(|w| w == name).unwrap() + 4;
+    for strictness in [Strictness::Strict, Strictness::Normal, Strictness::Lenient] {
+        read(&file, strictness).unwrap();
+    }
+
+    // infe flags other than hidden
+    let mut infe_flags = file.clone();
+    infe_flags[find(b"infe") + 3] = 2;
+    assert!(matches!(read(&infe_flags, Strictness::Strict).unwrap_err().error, Error::InvalidData(_)));
+    read(&infe_flags, Strictness::Normal).unwrap();
+
+    // hidden primary item
+    let mut hidden = file.clone();
+    hidden[find(b"infe") + 3] = 1;
+    assert!(matches!(read(&hidden, Strictness::Normal).unwrap_err().error, Error::InvalidData(_)));
+    let data = read(&hidden, Strictness::Lenient).unwrap();
+    assert_eq!(data.warnings.iter().map(|w| w.code).collect::<Vec<_>>(), [WarningCode::HiddenPrimaryItem]);
+
+    // mdat size larger than the file
+    let mut mdat_size = file.clone();
+    mdat_size[find(b"mdat") - 5] += 1;
+    assert!(matches!(read(&mdat_size, Strictness::Strict).unwrap_err().error, Error::InvalidData(_)));
+    read(&mdat_size, Strictness::Normal).unwrap();
+    let mut options = ParseOptions::default();
+    options.strictness = Strictness::Strict;
+    assert!(matches!(avif_parse::read_avif_slice_with_options(&mdat_size, &options), Err(ErrorContext { error: Error::InvalidData("mdat is truncated"), .. })));
+    let seeked = avif_parse::read_avif_seekable_with_options(&mut std::io::Cursor::new(&mdat_size), &options);
+    assert!(matches!(seeked, Err(ErrorContext { error: Error::InvalidData("mdat is truncated"), .. })));
+    let mut parser = AvifPushParser::with_options(&options);
+    parser.push(&mdat_size).unwrap();
+    assert!(matches!(parser.into_avif_data(), Err(Error::InvalidData("mdat is truncated"))));
+    let mut parser = AvifPushParser::with_options(&options);
+    parser.push(&file).unwrap();
+    parser.into_avif_data().unwrap();
+    avif_parse::read_avif_slice(&mdat_size).unwrap();
+    avif_parse::read_avif_seekable(&mut std::io::Cursor::new(&mdat_size)).unwrap();
+}
+
+#[test]
+fn parse_warnings() {
+    for path in [IMAGE_AVIF_EXTENTS, "tests/hdr-clli.avif", "tests/hdr-mdcv.avif", "tests/hdr-clli-mdcv.avif"] {
+        let file = std::fs::read(path).unwrap();
+        assert_eq!(avif_parse::read_avif(&mut file.as_slice()).unwrap().warnings.len(), 0, "{path}");
+    }
+
+    let mut file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    let infe = file.windows(4).position(|w| w == b"infe").unwrap() - 4;
+    file[infe + 11] = 2;
+    let header = avif_parse::AvifHeader::from_reader(file.as_slice()).unwrap();
+    assert_eq!(header.warnings().len(), 1);
+    let data = header.read_avif().unwrap();
+    let warning = &data.warnings[0];
+    assert_eq!(warning.code, WarningCode::UnexpectedFlags);
+    assert_eq!(warning.box_path.as_slice(), [*b"meta", *b"iinf", *b"infe"]);
+    assert_eq!(warning.offset, infe as u64);
+    assert_eq!(warning.to_string(), format!("infe flags other than hidden should be 0 in 'meta/iinf/infe' at {infe}"));
+
+    let slice_data = avif_parse::read_avif_slice(&file).unwrap();
+    assert_eq!(slice_data.warnings[..], data.warnings[..]);
+    let mut parser = AvifPushParser::new();
+    parser.push(&file).unwrap();
+    assert_eq!(parser.into_avif_data().unwrap().warnings[..], data.warnings[..]);
+
+    // Properties with unknown versions are ignored
+    let mut file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    let ispe = file.windows(4).position(|w| w == b"ispe").unwrap() + 4;
+    file[ispe] = 1;
+    let data = avif_parse::read_avif(&mut file.as_slice()).unwrap();
+    assert_eq!(data.warnings.iter().map(|w| w.code).collect::<Vec<_>>(), [WarningCode::UnexpectedFlags]);
+    assert_eq!(data.primary_spatial_extents, None);
+
+    // Bytes after the ipma entries are dropped
+    let mut file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    file.splice(188..188, [0; 4]);
+    for (size_offset, new_size) in [(20, 172u32), (119, 73), (167, 25)] {
+        file[size_offset..size_offset + 4].copy_from_slice(&new_size.to_be_bytes());
+    }
+    let iloc = file.windows(4).position(|w| w == b"iloc").unwrap() + 4;
+    for extent_offset in [iloc + 14, iloc + 22] {
+        let offset = u32::from_be_bytes(file[extent_offset..extent_offset + 4].try_into().unwrap()) + 4;
+        file[extent_offset..extent_offset + 4].copy_from_slice(&offset.to_be_bytes());
+    }
+    let data = avif_parse::read_avif(&mut file.as_slice()).unwrap();
+    assert_eq!(data.warnings.len(), 1);
+    assert_eq!(data.warnings[0].code, WarningCode::DroppedBoxContent);
+    assert_eq!(data.warnings[0].box_path.as_slice(), [*b"meta", *b"iprp", *b"ipma"]);
+}
+
+#[test]
+fn error_context() {
+    let mut file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    let pitm = file.windows(4).position(|w| w == b"pitm").unwrap() - 4;
+    file[pitm + 8] = 2; // version
+    let err = avif_parse::read_avif(&mut file.as_slice()).unwrap_err();
+    assert!(matches!(err, Error::Unsupported(_)));
+    assert_eq!(err.to_string(), "unsupported version 2 of 'pitm' box");
+    let options = ParseOptions::default();
+    let context = avif_parse::read_avif_with_options(&mut file.as_slice(), &options).unwrap_err();
+    assert!(matches!(context.error, Error::Unsupported(_)));
+    assert_eq!(context.box_path.as_slice(), [*b"meta", *b"pitm"]);
+    assert_eq!(context.offset, pitm as u64);
+    assert_eq!(context.to_string(), format!("unsupported version 2 of 'pitm' box in 'meta/pitm' at {pitm}"));
+
+    // Every reader taking options reports the location
+    let located = |context: ErrorContext| (context.box_path.as_slice().to_vec(), context.offset);
+    let expected = located(context);
+    assert_eq!(located(avif_parse::read_avif_slice_with_options(&file, &options).unwrap_err()), expected);
+    assert_eq!(located(avif_parse::read_avif_seekable_with_options(&mut std::io::Cursor::new(&file), &options).unwrap_err()), expected);
+    assert_eq!(located(avif_parse::AvifHeader::from_reader_with_options(file.as_slice(), &options).err().unwrap()), expected);
+    assert_eq!(located(AvifPushParser::with_options(&options).push(&file).unwrap_err()), expected);
+
+    let mut no_pitm = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    let pitm = no_pitm.windows(4).position(|w| w == b"pitm").unwrap();
+    no_pitm[pitm..pitm + 4].copy_from_slice(b"free");
+    let context = avif_parse::read_avif_slice_with_options(&no_pitm, &options).unwrap_err();
+    assert_eq!(context.box_path.as_slice(), [*b"meta"]);
+    assert_eq!(context.offset, 20);
+
+    // Errors outside of boxes have no location
+    let mut options = ParseOptions::default();
+    options.max_alloc_size = Some(1000);
+    let context = avif_parse::read_avif_with_options(&mut std::fs::read(IMAGE_AVIF_EXTENTS).unwrap().as_slice(), &options).unwrap_err();
+    assert!(matches!(context.error, Error::LimitExceeded("max_alloc_size")));
+    assert!(context.box_path.as_slice().is_empty());
+}
+
+#[test]
+fn unsupported_features() {
+    let file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    let find = |name: &[u8]| file.windows(4).position(|w| w == name).unwrap() + 4;
+    let unsupported = |file: &[u8]| match avif_parse::read_avif(&mut &file[..]).unwrap_err() {
+        Error::Unsupported(feature) => feature,
+        err => panic!("{err}"),
+    };
+
+    let mut animated = file.clone();
+    animated[find(b"ftyp")..][..4].copy_from_slice(b"avis");
+    assert_eq!(unsupported(&animated), UnsupportedFeature::Animation);
+
+    let mut grid = file.clone();
+    grid[find(b"infe") + 8..][..4].copy_from_slice(b"grid");
+    assert_eq!(unsupported(&grid), UnsupportedFeature::DerivedImage(*b"grid"));
+    let mut overlay = file.clone();
+    overlay[find(b"infe") + 8..][..4].copy_from_slice(b"iovl");
+    assert_eq!(unsupported(&overlay), UnsupportedFeature::DerivedImage(*b"iovl"));
+    let mut identity = file.clone();
+    identity[find(b"infe") + 8..][..4].copy_from_slice(b"iden");
+    assert_eq!(unsupported(&identity), UnsupportedFeature::DerivedImage(*b"iden"));
+
+    let mut protected = file.clone();
+    protected[find(b"infe") + 7] = 1;
+    assert_eq!(unsupported(&protected), UnsupportedFeature::Protection);
+
+    let mut version = file.clone();
+    version[find(b"pitm")] = 2;
+    assert_eq!(unsupported(&version), UnsupportedFeature::BoxVersion { box_type: *b"pitm", version: 2 });
+}
+
+#[test]
+fn item_catalogue() {
+    let file = std::fs::read(IMAGE_AVIF_CORRUPT_2).unwrap();
+    let header = avif_parse::AvifHeader::from_reader(file.as_slice()).unwrap();
+    assert_eq!(header.primary_item_id(), 1);
+    let items: Vec<_> = header.items().collect();
+    assert_eq!(items.iter().map(|i| (i.id(), i.item_type(), i.is_hidden())).collect::<Vec<_>>(), [
+        (1, *b"av01", false),
+        (2, *b"av01", true),
+        (3, *b"Exif", true),
+    ]);
+
+    let primary = header.item(1).unwrap();
+    assert_eq!(primary.construction_method(), Some(avif_parse::ConstructionMethod::File));
+    assert_eq!(primary.extents().collect::<Vec<_>>(), [ItemExtent { start: 1049118, end: Some(1053626) }]);
+    assert_eq!(primary.references().count(), 0);
+    let auxl = ItemReference { reference_type: *b"auxl", from_item_id: 2, to_item_id: 1 };
+    let cdsc = ItemReference { reference_type: *b"cdsc", from_item_id: 3, to_item_id: 1 };
+    assert_eq!(primary.referenced_by().collect::<Vec<_>>(), [auxl, cdsc]);
+    assert!(primary.properties().any(|p| matches!(p, Property::ImageSpatialExtents(_))));
+
+    let alpha = header.item(2).unwrap();
+    assert_eq!(alpha.references().collect::<Vec<_>>(), [auxl]);
+    assert!(alpha.properties().any(|p| matches!(p, Property::AuxiliaryType { aux_type: b"urn:mpeg:mpegB:cicp:systems:auxiliary:alpha", .. })));
+    assert_eq!(header.item(3).unwrap().name(), b"");
+    assert_eq!(header.item(3).unwrap().content_type(), None);
+    assert!(header.item(4).is_none());
+}
+
+#[test]
+fn read_items_by_id() {
+    let file = std::fs::read(IMAGE_AVIF_CORRUPT_2).unwrap();
+    let header = avif_parse::AvifHeader::from_reader(file.as_slice()).unwrap();
+    let exif = header.read_item(3).unwrap();
+    assert_eq!(&exif[..], &file[5050..5266]);
+
+    let header = avif_parse::AvifHeader::from_reader(file.as_slice()).unwrap();
+    assert!(header.read_item(4).is_err());
+
+    let file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    let avif = avif_parse::read_avif(&mut file.as_slice()).unwrap();
+    let header = avif_parse::AvifHeader::from_reader(file.as_slice()).unwrap();
+    let items = header.read_items(&[1, 1]).unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(&items[0][..], &avif.primary_item[..]);
+    assert_eq!(&items[1][..], &items[0][..]);
+
+    let file = std::fs::read(IMAGE_AVIF_IDAT).unwrap();
+    let exif = b"\0\0\0\0MM\0*\0\0\0\x08\0\0";
+    let header = avif_parse::AvifHeader::from_reader(file.as_slice()).unwrap();
+    assert_eq!(header.item(2).unwrap().construction_method(), Some(avif_parse::ConstructionMethod::Idat));
+    assert_eq!(&header.read_item(2).unwrap()[..], exif);
+    let header = avif_parse::AvifHeader::from_reader(file.as_slice()).unwrap();
+    let items = header.read_items(&[2, 1]).unwrap();
+    assert_eq!(&items[0][..], exif);
+    assert_eq!(&items[1][..], &avif.primary_item[..]);
+
+    // Other readers copy idat items too
+    let header = avif_parse::AvifHeader::from_seekable_reader(std::io::Cursor::new(&file)).unwrap();
+    assert_eq!(&header.read_items(&[2]).unwrap()[0][..], exif);
+    let seeked = avif_parse::AvifHeader::from_seekable_reader(std::io::Cursor::new(&file)).unwrap()
+        .load_metadata(true)
+        .read_avif_seek()
+        .unwrap();
+    assert_eq!(&seeked.primary_item[..], &avif.primary_item[..]);
+    assert_eq!(seeked.other_items.len(), 1);
+    assert_eq!(&seeked.other_items[0].1[..], exif);
+    let header = avif_parse::AvifHeader::from_reader(&file[..283]).unwrap().load_metadata(true);
+    let ranges = header.item_byte_ranges().unwrap();
+    assert!(ranges.iter().all(|r| r.item_id == 1));
+    assert_eq!(&header.read_item_from_ranges(2, &[]).unwrap()[..], exif);
+}
+
+#[test]
+fn item_selection() {
+    let file = std::fs::read(IMAGE_AVIF_CORRUPT_2).unwrap();
+    // The limit is smaller than the mdat, so only the selected extents fit
+    let mut options = ParseOptions::default();
+    options.max_alloc_size = Some(4000);
+    let avif = avif_parse::AvifHeader::from_reader_with_options(file.as_slice(), &options).unwrap()
+        .load_primary(false)
+        .load_alpha(false)
+        .load_metadata(true)
+        .read_avif()
+        .unwrap();
+    assert!(avif.primary_item.is_empty());
+    assert!(avif.alpha_item.is_none());
+    assert_eq!(avif.other_items.len(), 1);
+    assert_eq!(avif.other_items[0].0, 3);
+    assert_eq!(&avif.other_items[0].1[..], &file[5050..5266]);
+
+    let header = avif_parse::AvifHeader::from_reader(file.as_slice()).unwrap();
+    assert!(header.load_item(4).is_err());
+
+    let file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    let avif = avif_parse::read_avif(&mut file.as_slice()).unwrap();
+    let selected = avif_parse::AvifHeader::from_reader(file.as_slice()).unwrap()
+        .load_alpha(false)
+        .load_thumbnails(true)
+        .read_avif()
+        .unwrap();
+    assert_eq!(&selected.primary_item[..], &avif.primary_item[..]);
+    assert!(selected.other_items.is_empty());
+}
+
+#[test]
+fn protected_items() {
+    let original = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    let find = |name: &[u8]| original.windows(4).position(|w| w == name).unwrap() + 4;
+    let be_box = |name: &[u8; 4], content: &[u8]| [&(content.len() as u32 + 8).to_be_bytes()[..], name, content].concat();
+
+    let key_id = *b"0123456789abcdef";
+    let tenc = be_box(b"tenc", &[&[0, 0, 0, 0, 0, 0, 1, 16][..], &key_id].concat());
+    let sinf = be_box(b"sinf", &[
+        be_box(b"frma", b"av01"),
+        be_box(b"schm", &[&[0, 0, 0, 0][..], b"cenc", &0x1_0000u32.to_be_bytes()].concat()),
+        be_box(b"schi", &tenc),
+    ].concat());
+    let ipro = be_box(b"ipro", &[&[0, 0, 0, 0, 0, 1][..], &sinf].concat());
+
+    // ipro is appended to meta, moving mdat and the item's extents
+    let meta = find(b"meta") - 8;
+    let meta_end = meta + u32::from_be_bytes(original[meta..meta + 4].try_into().unwrap()) as usize;
+    let mut file = [&original[..meta_end], &ipro, &original[meta_end..]].concat();
+    let add = |file: &mut [u8], pos: usize, n: usize| {
+        let value = u32::from_be_bytes(file[pos..pos + 4].try_into().unwrap()) + n as u32;
+        file[pos..pos + 4].copy_from_slice(&value.to_be_bytes());
+    };
+    add(&mut file, meta, ipro.len());
+    let iloc = find(b"iloc");
+    add(&mut file, iloc + 14, ipro.len());
+    add(&mut file, iloc + 22, ipro.len());
+    let infe = find(b"infe");
+    file[infe + 7] = 1;
+    file[infe + 8..][..4].copy_from_slice(b"encv");
+
+    let header = avif_parse::AvifHeader::from_reader(file.as_slice()).unwrap();
+    let item = header.item(1).unwrap();
+    assert!(item.is_protected());
+    assert_eq!(item.item_type(), *b"encv");
+    let scheme = item.protection().unwrap();
+    assert_eq!(scheme.original_format, Some(*b"av01"));
+    assert_eq!(scheme.scheme_type, *b"cenc");
+    assert_eq!(scheme.scheme_version, 0x1_0000);
+    assert_eq!(scheme.key_id, Some(key_id));
+    assert_eq!(scheme.per_sample_iv_size, 16);
+
+    let encrypted = header.read_item(1).unwrap();
+    let avif = avif_parse::read_avif(&mut original.as_slice()).unwrap();
+    assert_eq!(&encrypted[..], &avif.primary_item[..]);
+
+    let err = avif_parse::read_avif(&mut file.as_slice()).unwrap_err();
+    assert!(matches!(err, Error::Unsupported(UnsupportedFeature::Protection)));
+}
+
+#[test]
+fn file_type_brands() {
+    let file = std::fs::read("tests/hdr-clli.avif").unwrap();
+    let avif = avif_parse::read_avif(&mut file.as_slice()).unwrap();
+    assert_eq!(avif.file_type.major_brand, *b"avif");
+    assert_eq!(avif.file_type.compatible_brands[..], [*b"mif1", *b"miaf"]);
+    assert!(avif.file_type.is_miaf());
+    assert!(!avif.file_type.is_intra_only());
+    assert_eq!(avif.file_type.avif_profile(), None);
+    assert_eq!(avif.check_profile().unwrap().len(), 0);
+
+    let mut file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    file[16..20].copy_from_slice(b"MA1B");
+    let header = avif_parse::AvifHeader::from_reader(file.as_slice()).unwrap();
+    assert_eq!(header.file_type().compatible_brands[..], [*b"MA1B"]);
+    let avif = header.read_avif().unwrap();
+    assert_eq!(avif.file_type.avif_profile(), Some(AvifProfile::Baseline));
+    let meta = avif.primary_item_metadata().unwrap();
+    assert_eq!((meta.seq_profile, meta.seq_level_idx), (1, 31));
+    let violations: Vec<_> = avif.check_profile().unwrap().iter().map(|d| (d.item, d.violation)).collect();
+    assert_eq!(violations, [
+        (ItemRole::Primary, ProfileViolation::SeqProfile { profile: AvifProfile::Baseline, seq_profile: 1 }),
+        (ItemRole::Primary, ProfileViolation::Level { profile: AvifProfile::Baseline, seq_level_idx: 31 }),
+    ]);
+
+    // High profile is allowed in Advanced, and reduced_still_picture_header is patched to seq_level_idx 13 (level 5.1)
+    file[16..20].copy_from_slice(b"MA1A");
+    let sequence_header = 0xc4 + 4;
+    file[sequence_header..][..2].copy_from_slice(&[0x3b, 0x59]);
+    let avif = avif_parse::read_avif(&mut file.as_slice()).unwrap();
+    assert_eq!(avif.file_type.avif_profile(), Some(AvifProfile::Advanced));
+    assert_eq!(avif.primary_item_metadata().unwrap().seq_level_idx, 13);
+    assert_eq!(avif.check_profile().unwrap().len(), 0);
+}
+
+#[test]
+fn compatible_avif_brand() {
+    let mut file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    assert_eq!(avif_parse::AvifHeader::from_reader(file.as_slice()).unwrap().file_type().avif_brand(), Some(AvifBrand::Major));
+
+    file[8..12].copy_from_slice(b"miaf");
+    let err = avif_parse::read_avif(&mut file.as_slice()).unwrap_err();
+    assert!(matches!(err, Error::InvalidData(_)));
+
+    // major brand miaf, compatible brand avif
+    file[16..20].copy_from_slice(b"avif");
+    let header = avif_parse::AvifHeader::from_reader(file.as_slice()).unwrap();
+    assert_eq!(header.file_type().major_brand, *b"miaf");
+    assert_eq!(header.file_type().avif_brand(), Some(AvifBrand::Compatible));
+    assert_eq!(header.warnings().iter().map(|w| w.code).collect::<Vec<_>>(), [WarningCode::CompatibleBrand]);
+    let avif = avif_parse::read_avif_slice(&file).unwrap();
+    assert_eq!(avif.file_type.compatible_brands[..], [*b"avif"]);
+    assert!(avif.file_type.is_miaf());
+
+    // Animated AVIF with a still image fallback
+    file[8..12].copy_from_slice(b"avis");
+    let err = avif_parse::read_avif(&mut file.as_slice()).unwrap_err();
+    assert!(matches!(err, Error::Unsupported(UnsupportedFeature::Animation)));
+}
+
+#[test]
+fn probe() {
+    let mut file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    let probe = avif_parse::probe(&file[..512]);
+    assert_eq!(probe.format, FileFormat::AvifStill);
+    assert_eq!(probe.file_type.unwrap().major_brand, *b"avif");
+    assert_eq!((probe.has_alpha, probe.has_grid, probe.is_animated), (Some(false), Some(false), Some(false)));
+
+    // meta is cut off
+    let probe = avif_parse::probe(&file[..100]);
+    assert_eq!(probe.format, FileFormat::AvifStill);
+    assert_eq!((probe.has_alpha, probe.has_grid), (None, None));
+
+    file[8..12].copy_from_slice(b"avis");
+    let probe = avif_parse::probe(&file);
+    assert_eq!((probe.format, probe.is_animated), (FileFormat::AvifSequence, Some(true)));
+
+    file[8..12].copy_from_slice(b"heic");
+    assert_eq!(avif_parse::probe(&file).format, FileFormat::Heic);
+
+    file[8..12].copy_from_slice(b"mp42");
+    assert_eq!(avif_parse::probe(&file).format, FileFormat::OtherIsobmff);
+    assert_eq!(avif_parse::probe(&file[..12]).format, FileFormat::OtherIsobmff);
+
+    assert_eq!(avif_parse::probe(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").format, FileFormat::Unknown);
+    assert_eq!(avif_parse::probe(&[]).format, FileFormat::Unknown);
+}
+
+#[test]
+fn dump_boxes() {
+    fn types(boxes: &[BoxNode]) -> Vec<[u8; 4]> {
+        boxes.iter().map(|b| b.box_type).collect()
+    }
+
+    let mut file = std::fs::read(IMAGE_AVIF_EXTENTS).unwrap();
+    let tree = avif_parse::dump_boxes(&file).unwrap();
+    assert!(tree.error.is_none());
+    assert_eq!(types(&tree.boxes), [*b"ftyp", *b"meta", *b"mdat"]);
+    let meta = &tree.boxes[1];
+    assert_eq!((meta.offset, meta.header_size, meta.payload_size, meta.version_flags), (20, 8, 160, Some((0, 0))));
+    assert_eq!(types(&meta.children), [*b"pitm", *b"iloc", *b"iinf", *b"iprp"]);
+    assert!(matches!(meta.children[0].fields, BoxFields::PrimaryItem(1)));
+    let BoxFields::ItemLocations(locations) = &meta.children[1].fields else { panic!() };
+    assert_eq!(locations[0].extents[0], ItemExtent { start: 0xc4, end: Some(0xc4 + 0x891) });
+    assert!(matches!(meta.children[2].children[0].fields, BoxFields::ItemInfo { item_id: 1, item_type: [b'a', b'v', b'0', b'1'], .. }));
+    let mdat = &tree.boxes[2];
+    assert_eq!((mdat.offset, mdat.header_size, mdat.payload_size, mdat.truncated), (188, 8, 4387, false));
+
+    // Cut off
+    let tree = avif_parse::dump_boxes(&file[..1000]).unwrap();
+    assert_eq!((tree.boxes[2].payload_size, tree.boxes[2].truncated), (4387, true));
+
+    // Last box with size 0 extends to the end of the file
+    file[188..192].copy_from_slice(&[0; 4]);
+    let tree = avif_parse::dump_boxes(&file).unwrap();
+    assert!(tree.error.is_none());
+    assert_eq!((tree.boxes[2].box_type, tree.boxes[2].payload_size), (*b"mdat", 4387));
+
+    // 64-bit size, followed by garbage
+    let mut file = file[..20].to_vec();
+    file.extend_from_slice(&[0, 0, 0, 1]);
+    file.extend_from_slice(b"free");
+    file.extend_from_slice(&24u64.to_be_bytes());
+    file.extend_from_slice(&[0; 8 + 3]);
+    let tree = avif_parse::dump_boxes(&file).unwrap();
+    assert_eq!(types(&tree.boxes), [*b"ftyp", *b"free"]);
+    assert_eq!((tree.boxes[1].header_size, tree.boxes[1].payload_size), (16, 8));
+    assert!(matches!(tree.error.unwrap(), Error::UnexpectedEOF));
+
+    let tree = avif_parse::dump_boxes(&std::fs::read(IMAGE_AVIF_CORRUPT_2).unwrap()).unwrap();
+    let iref = tree.boxes[1].children.iter().find(|b| b.box_type == *b"iref").unwrap();
+    assert_eq!(types(&iref.children), [*b"auxl", *b"cdsc"]);
+    let BoxFields::ItemReference { from_item_id, to_item_ids } = &iref.children[0].fields else { panic!() };
+    assert_eq!((*from_item_id, &to_item_ids[..]), (2, &[1][..]));
+}